@@ -80,7 +80,7 @@
             commonArgs
             // {
               inherit cargoArtifacts;
-              cargoClippyExtraArgs = "--all-targets -- --deny warnings";
+              cargoClippyExtraArgs = "--all-targets --all-features -- --deny warnings";
             }
           );
 
@@ -88,6 +88,7 @@
             commonArgs
             // {
               inherit cargoArtifacts;
+              cargoDocExtraArgs = "--no-deps --all-features";
               # This can be commented out or tweaked as necessary, e.g. set to
               # `--deny rustdoc::broken-intra-doc-links` to only enforce that lint
               env.RUSTDOCFLAGS = "--deny warnings";
@@ -125,6 +126,7 @@
               partitions = 1;
               partitionType = "count";
               cargoNextestPartitionsExtraArgs = "--no-tests=pass";
+              cargoNextestExtraArgs = "--all-features";
             }
           );
         };
@@ -0,0 +1,522 @@
+//! An async [`PkdClient`] that sends [`Directory`] calls with [`reqwest`]
+
+use std::time::Duration;
+
+use pkd_core::{
+    Clock, KeyId, MerkleRoot, PublicKey, SystemClock,
+    action::{ActorId, RevocationToken},
+    api,
+};
+
+use crate::{Call, Directory, RequestError, ResponseError};
+
+/// Errors from a [`PkdClient`]
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    /// The request couldn't be built
+    #[error(transparent)]
+    Request(#[from] RequestError),
+    /// The directory's response was an error or couldn't be parsed.
+    ///
+    /// This is [`ResponseError::RateLimited`] if the directory is still rate-limiting after every retry.
+    #[error(transparent)]
+    Response(#[from] ResponseError),
+    /// The request couldn't be sent or the response couldn't be read, including timeouts
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+/// How a [`PkdClient`] retries requests that the directory rate-limited with an HTTP 429
+///
+/// The delay starts at `initial` and doubles after each retry, mirroring the directory's own
+/// [penalty](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#rate-limiting-bad-requests).
+/// A longer `Retry-After` from the directory takes precedence.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Backoff {
+    /// The delay before the first retry
+    pub initial: Duration,
+    /// The longest delay between retries.
+    ///
+    /// If the directory asks to wait longer than this, the client gives up instead.
+    pub max: Duration,
+    /// How many times a request is retried
+    pub retries: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#rate-limiting-bad-requests
+        //# Each value of the counter doubles the amount of time that must pass between subsequent
+        //# requests, beginning with a configurable parameter (default = 100 milliseconds) for the first failure.
+        Self {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(60),
+            retries: 5,
+        }
+    }
+}
+
+impl Backoff {
+    /// The delay before retry number `attempt`, counting from 0, or `None` if `retry_after` is longer than `max`
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        let retry_after = retry_after.unwrap_or_default();
+        if retry_after > self.max {
+            return None;
+        }
+        let exponential = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max);
+        Some(exponential.max(retry_after))
+    }
+}
+
+/// An async client for a Public Key Directory
+///
+/// Protocol messages are delivered by the actor's instance over ActivityPub, which adds the HTTP Signatures the
+/// directory requires, so this only covers the JSON REST API.
+///
+/// Response signatures must be recent according to the client's [`Clock`], the system clock unless set with
+/// [`PkdClient::with_clock`].
+///
+/// # Example
+/// ```no_run
+/// # async fn example() -> Result<(), pkd_client::ClientError> {
+/// use pkd_client::PkdClient;
+///
+/// let key = "ed25519:Tm2XBvb0mAb4ldVubCzvz0HMTczR8VGF44sv478VFLM".parse().unwrap();
+/// let client = PkdClient::new("https://pkd.example.com", key)?;
+/// let keys = client
+///     .actor_keys(&"https://example.com/users/alice".parse().unwrap())
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PkdClient<C = SystemClock> {
+    http: reqwest::Client,
+    directory: Directory,
+    timeout: Option<Duration>,
+    backoff: Backoff,
+    clock: C,
+}
+
+impl PkdClient {
+    /// The default timeout for each attempt of a request
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// A client for the directory at `base` that signs its responses with `public_key`, see [`Directory::new`]
+    pub fn new(base: &str, public_key: PublicKey) -> Result<Self, ClientError> {
+        Ok(Self::from_directory(Directory::new(base, public_key)?))
+    }
+
+    /// A client for `directory`
+    pub fn from_directory(directory: Directory) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            directory,
+            timeout: Some(Self::DEFAULT_TIMEOUT),
+            backoff: Backoff::default(),
+            clock: SystemClock,
+        }
+    }
+}
+
+impl<C: Clock> PkdClient<C> {
+    /// Tell the time with `clock`, e.g. to check response signatures against a fixed time in tests
+    pub fn with_clock<D: Clock>(self, clock: D) -> PkdClient<D> {
+        PkdClient {
+            http: self.http,
+            directory: self.directory,
+            timeout: self.timeout,
+            backoff: self.backoff,
+            clock,
+        }
+    }
+
+    /// Send requests with `http`, e.g. to configure proxies or connection timeouts
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Limit each attempt of a request to `timeout`, or not at all if `None`
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retry rate-limited requests with `backoff`
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// The [`Directory`] this client sends requests to
+    pub fn directory(&self) -> &Directory {
+        &self.directory
+    }
+
+    /// A client for the replica of another directory with `replica_id`, see [`Directory::replica`]
+    pub fn replica(&self, replica_id: &str) -> Self
+    where
+        C: Clone,
+    {
+        Self {
+            directory: self.directory.replica(replica_id),
+            ..self.clone()
+        }
+    }
+
+    /// Send `call`, retrying while the directory is rate-limiting, and parse its response
+    ///
+    /// The response's signature must be recent according to the client's clock.
+    pub async fn send<T>(&self, call: Call<T>) -> Result<T, ClientError> {
+        let mut attempt = 0;
+        loop {
+            let response = self.execute(call.request()).await?;
+            match call.parse(&response, &self.clock) {
+                Err(ResponseError::RateLimited { retry_after })
+                    if attempt < self.backoff.retries =>
+                {
+                    let Some(delay) = self.backoff.delay(attempt, retry_after) else {
+                        return Err(ResponseError::RateLimited { retry_after }.into());
+                    };
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return Ok(result?),
+            }
+        }
+    }
+
+    async fn execute(
+        &self,
+        request: &http::Request<Vec<u8>>,
+    ) -> Result<http::Response<Vec<u8>>, reqwest::Error> {
+        let mut builder = self
+            .http
+            .request(request.method().clone(), request.uri().to_string())
+            .headers(request.headers().clone())
+            .body(request.body().clone());
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        let response = builder.send().await?;
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let mut parsed = http::Response::new(response.bytes().await?.to_vec());
+        *parsed.status_mut() = status;
+        *parsed.headers_mut() = headers;
+        Ok(parsed)
+    }
+
+    /// Fetch aggregate data about `actor`, see [`Directory::actor_info`]
+    pub async fn actor_info(&self, actor: &ActorId) -> Result<api::ActorInfo, ClientError> {
+        self.send(self.directory.actor_info(actor)?).await
+    }
+
+    /// Fetch the public keys of `actor`, see [`Directory::actor_keys`]
+    pub async fn actor_keys(&self, actor: &ActorId) -> Result<api::ActorKeys, ClientError> {
+        self.send(self.directory.actor_keys(actor)?).await
+    }
+
+    /// Fetch a public key of `actor`, see [`Directory::key_info`]
+    pub async fn key_info(
+        &self,
+        actor: &ActorId,
+        key_id: &KeyId,
+    ) -> Result<api::KeyInfo, ClientError> {
+        self.send(self.directory.key_info(actor, key_id)?).await
+    }
+
+    /// Fetch the auxiliary data of `actor`, see [`Directory::actor_auxiliary`]
+    pub async fn actor_auxiliary(
+        &self,
+        actor: &ActorId,
+    ) -> Result<api::ActorAuxiliary, ClientError> {
+        self.send(self.directory.actor_auxiliary(actor)?).await
+    }
+
+    /// Fetch an auxiliary data record of `actor`, see [`Directory::aux_info`]
+    pub async fn aux_info(
+        &self,
+        actor: &ActorId,
+        aux_id: &str,
+    ) -> Result<api::AuxInfo, ClientError> {
+        self.send(self.directory.aux_info(actor, aux_id)?).await
+    }
+
+    /// Fetch the latest Merkle root, see [`Directory::history`]
+    pub async fn history(&self) -> Result<api::History, ClientError> {
+        self.send(self.directory.history()?).await
+    }
+
+    /// Fetch the records after `last`, see [`Directory::history_since`]
+    pub async fn history_since(&self, last: &MerkleRoot) -> Result<api::HistorySince, ClientError> {
+        self.send(self.directory.history_since(last)?).await
+    }
+
+    /// Fetch the record with Merkle root `root`, see [`Directory::history_view`]
+    pub async fn history_view(&self, root: &MerkleRoot) -> Result<api::HistoryView, ClientError> {
+        self.send(self.directory.history_view(root)?).await
+    }
+
+    /// Fetch the supported Auxiliary Data extensions, see [`Directory::extensions`]
+    pub async fn extensions(&self) -> Result<api::Extensions, ClientError> {
+        self.send(self.directory.extensions()?).await
+    }
+
+    /// Fetch the replicated directories, see [`Directory::replicas`]
+    pub async fn replicas(&self) -> Result<api::Replicas, ClientError> {
+        self.send(self.directory.replicas()?).await
+    }
+
+    /// Fetch the directory's HPKE public key, see [`Directory::server_public_key`]
+    pub async fn server_public_key(&self) -> Result<api::ServerPublicKey, ClientError> {
+        self.send(self.directory.server_public_key()?).await
+    }
+
+    /// Revoke a public key with `token` at the current time of the client's clock, see [`Directory::revoke`]
+    pub async fn revoke(
+        &self,
+        token: RevocationToken,
+    ) -> Result<Option<api::Revoked>, ClientError> {
+        self.send(self.directory.revoke(token, &self.clock)?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::signature::testing::{NOW, alice, key, sign};
+
+    const KEYS: &str = r#"{"!pkd-context":"fedi-e2ee:v1/api/actor/get-keys","actor-id":"https://example.com/users/alice","public-keys":[]}"#;
+
+    /// A canned response from [`serve`]: status, extra headers and body
+    type Canned = (u16, &'static str, &'static str);
+
+    /// Serve `responses` in order on a local port, one per connection, returning the base URL and the request lines
+    /// received so far.
+    ///
+    /// Every response is signed with [`key`] at [`NOW`]. A status of 0 accepts the connection but never responds.
+    async fn serve(responses: Vec<Canned>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            for (status, headers, body) in responses {
+                // Connections closed before sending a request don't consume a response
+                let (mut stream, head) = loop {
+                    let (mut stream, _) = listener.accept().await.unwrap();
+                    if let Some(head) = read_head(&mut stream).await {
+                        break (stream, head);
+                    }
+                };
+                seen.lock()
+                    .unwrap()
+                    .push(head.lines().next().unwrap().to_string());
+                if status == 0 {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    continue;
+                }
+                let mut signed = http::Response::new(body);
+                *signed.status_mut() = http::StatusCode::from_u16(status).unwrap();
+                sign(&mut signed, &key());
+                let signature = signed
+                    .headers()
+                    .iter()
+                    .map(|(name, value)| format!("{name}: {}\r\n", value.to_str().unwrap()))
+                    .collect::<String>();
+                let response = format!(
+                    "HTTP/1.1 {status} X\r\ncontent-length: {}\r\nconnection: close\r\n{headers}{signature}\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (base, requests)
+    }
+
+    /// Read a request's head from `stream`, or [`None`] if it's closed first
+    async fn read_head(stream: &mut tokio::net::TcpStream) -> Option<String> {
+        let mut buf = Vec::new();
+        let mut chunk = [0; 1024];
+        while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+            let n = stream.read(&mut chunk).await.ok()?;
+            if n == 0 {
+                return None;
+            }
+            buf.extend_from_slice(&chunk[..n]);
+        }
+        Some(String::from_utf8_lossy(&buf).into_owned())
+    }
+
+    /// A client for the directory at `base`, with its clock at [`NOW`]
+    fn new_client(base: &str) -> PkdClient<pkd_core::Timestamp> {
+        PkdClient::new(base, key().public_key())
+            .unwrap()
+            .with_clock(NOW)
+    }
+
+    fn fast() -> Backoff {
+        Backoff {
+            initial: Duration::from_millis(1),
+            max: Duration::from_secs(1),
+            retries: 2,
+        }
+    }
+
+    #[test]
+    fn backoff() {
+        let backoff = Backoff::default();
+        let delays = (0..4)
+            .map(|attempt| backoff.delay(attempt, None).unwrap().as_millis())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [100, 200, 400, 800]);
+        assert_eq!(backoff.delay(40, None), Some(backoff.max));
+        assert_eq!(
+            backoff.delay(0, Some(Duration::from_secs(2))),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(backoff.delay(0, Some(Duration::from_secs(61))), None);
+    }
+
+    #[tokio::test]
+    async fn fetch() {
+        let (base, requests) = serve(vec![(200, "", KEYS)]).await;
+        // A connection closed without a request doesn't stall the server
+        drop(tokio::net::TcpStream::connect(base.trim_start_matches("http://")).await);
+        let keys = new_client(&base).actor_keys(&alice()).await.unwrap();
+        assert_eq!(keys.actor_id, alice());
+        assert_eq!(
+            *requests.lock().unwrap(),
+            ["GET /api/actor/https%3A%2F%2Fexample.com%2Fusers%2Falice/keys HTTP/1.1"]
+        );
+
+        // A response signed by anyone but the pinned key is rejected
+        let (base, _) = serve(vec![(200, "", KEYS)]).await;
+        let other = pkd_core::SigningKey::from_bytes(&[8; 32]).public_key();
+        let err = PkdClient::new(&base, other)
+            .unwrap()
+            .with_clock(NOW)
+            .actor_keys(&alice())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ClientError::Response(ResponseError::Signature(crate::HttpSignatureError::Invalid))
+            ),
+            "{err:?}"
+        );
+
+        // Signatures are checked against the client's clock, the system clock by default
+        let (base, _) = serve(vec![(200, "", KEYS)]).await;
+        let err = PkdClient::new(&base, key().public_key())
+            .unwrap()
+            .actor_keys(&alice())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ClientError::Response(ResponseError::Signature(crate::HttpSignatureError::Stale))
+            ),
+            "{err:?}"
+        );
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#rate-limiting-bad-requests
+    //= type=test
+    //# If insufficient time has elapsed, the request is dropped,
+    //# and the server responds with an HTTP 429 error.
+    #[tokio::test]
+    async fn rate_limited() {
+        let (base, requests) = serve(vec![
+            (429, "", ""),
+            (429, "retry-after: 0\r\n", ""),
+            (200, "", KEYS),
+        ])
+        .await;
+        let client = new_client(&base).with_backoff(fast());
+        client.actor_keys(&alice()).await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 3);
+
+        // Give up after the last retry
+        let (base, requests) = serve(vec![(429, "", ""); 3]).await;
+        let client = new_client(&base).with_backoff(fast());
+        let err = client.actor_keys(&alice()).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ClientError::Response(ResponseError::RateLimited { .. })
+            ),
+            "{err:?}"
+        );
+        assert_eq!(requests.lock().unwrap().len(), 3);
+
+        // Or when asked to wait longer than the backoff allows
+        let (base, requests) = serve(vec![(429, "retry-after: 120\r\n", "")]).await;
+        let client = new_client(&base).with_backoff(fast());
+        let err = client.actor_keys(&alice()).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ClientError::Response(ResponseError::RateLimited { retry_after: Some(d) })
+                    if d == Duration::from_secs(120)
+            ),
+            "{err:?}"
+        );
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn errors() {
+        // Other errors aren't retried
+        let (base, requests) = serve(vec![(404, "", ""), (500, "", "")]).await;
+        let client = new_client(&base).with_backoff(fast());
+        let err = client.actor_keys(&alice()).await.unwrap_err();
+        assert!(
+            matches!(err, ClientError::Response(ResponseError::NotFound)),
+            "{err:?}"
+        );
+        let err = client.history().await.unwrap_err();
+        assert!(
+            matches!(err, ClientError::Response(ResponseError::Status(s)) if s == 500),
+            "{err:?}"
+        );
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        let (base, _) = serve(vec![(0, "", "")]).await;
+        let client = new_client(&base).with_timeout(Some(Duration::from_millis(50)));
+        let err = client.history().await.unwrap_err();
+        assert!(
+            matches!(&err, ClientError::Http(e) if e.is_timeout()),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn revoke() {
+        let (base, requests) = serve(vec![(
+            200,
+            "",
+            r#"{"!pkd-context":"fedi-e2ee:v1/api/revoke","time":"1730909831"}"#,
+        )])
+        .await;
+        let token = RevocationToken::issue(&pkd_core::SigningKey::from_bytes(&[1; 32]));
+        let revoked = new_client(&base).revoke(token).await.unwrap();
+        assert!(revoked.is_some());
+        assert_eq!(*requests.lock().unwrap(), ["POST /api/revoke HTTP/1.1"]);
+    }
+}
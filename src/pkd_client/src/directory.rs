@@ -0,0 +1,867 @@
+//! Sans-IO requests to a Public Key Directory's [JSON REST API](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#json-rest-api)
+
+use std::{marker::PhantomData, time::Duration};
+
+use http::{HeaderMap, Method, Request, Response, StatusCode, Uri, header};
+use pkd_core::{
+    Clock, EncryptedMessage, KeyId, MerkleRoot, ProtocolMessage, PublicKey,
+    action::{Action, ActorId, RevocationToken},
+    api, json,
+};
+
+use crate::{HttpSignatureError, verify_response};
+
+const JSON: &str = "application/json";
+
+/// Errors from building a request
+#[derive(Debug, thiserror::Error)]
+pub enum RequestError {
+    /// The base URL isn't an absolute `http` or `https` URL without a query
+    #[error("invalid base URL: {0}")]
+    BaseUrl(String),
+    /// A path parameter produced an invalid URI
+    #[error(transparent)]
+    Uri(#[from] http::uri::InvalidUri),
+    /// The request couldn't be built
+    #[error(transparent)]
+    Http(#[from] http::Error),
+    /// The request body couldn't be serialized
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// A protocol message that must be signed has no signature
+    #[error("{0} message isn't signed")]
+    Unsigned(&'static str),
+}
+
+/// Errors from parsing a response
+#[derive(Debug, thiserror::Error)]
+pub enum ResponseError {
+    /// The directory has no data for the request, e.g. an unknown actor or a _Right To Be Forgotten_ takedown
+    #[error("not found")]
+    NotFound,
+    /// The directory is rate-limiting this client
+    #[error("rate limited")]
+    RateLimited {
+        /// How long the directory asked to wait, from the `Retry-After` header
+        retry_after: Option<Duration>,
+    },
+    /// The directory responded with an unexpected status
+    #[error("unexpected status {0}")]
+    Status(StatusCode),
+    /// The response isn't signed by the directory
+    #[error(transparent)]
+    Signature(#[from] HttpSignatureError),
+    /// The response's `actor-id`, `key-id` or `aux-id` differs from the request's, e.g. a replayed response for another
+    /// actor
+    #[error("response {0} doesn't match the request")]
+    Mismatch(&'static str),
+    /// The response body isn't UTF-8
+    #[error(transparent)]
+    Utf8(#[from] std::str::Utf8Error),
+    /// The response body isn't the expected JSON, including a mismatched `!pkd-context`
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// A Public Key Directory, or one of its replicas, at a base URL
+///
+/// Each method builds a [`Call`] holding the request to send and how to parse its response. Responses must be signed
+/// by the directory's pinned public key, and must echo the actor, key and auxiliary data identifiers of the request.
+///
+/// # Example
+/// ```
+/// use pkd_client::Directory;
+///
+/// let key = "ed25519:Tm2XBvb0mAb4ldVubCzvz0HMTczR8VGF44sv478VFLM".parse().unwrap();
+/// let directory = Directory::new("https://pkd.example.com", key).unwrap();
+/// let call = directory.actor_keys(&"https://example.com/users/alice".parse().unwrap()).unwrap();
+/// assert_eq!(
+///     call.request().uri(),
+///     "https://pkd.example.com/api/actor/https%3A%2F%2Fexample.com%2Fusers%2Falice/keys"
+/// );
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Directory {
+    /// `scheme://authority/path`, without a trailing slash
+    base: String,
+    /// The API path below `base`, without leading or trailing slashes
+    api: String,
+    /// The key that signs every response
+    public_key: PublicKey,
+}
+
+impl Directory {
+    /// A directory served from `base`, e.g. `https://pkd.example.com` or `https://example.com/pkd/`, that signs its
+    /// responses with `public_key`
+    pub fn new(base: &str, public_key: PublicKey) -> Result<Self, RequestError> {
+        let uri: Uri = base.parse()?;
+        let (Some(scheme), Some(authority)) = (uri.scheme_str(), uri.authority()) else {
+            return Err(RequestError::BaseUrl(base.to_string()));
+        };
+        if !matches!(scheme, "http" | "https") || uri.query().is_some() {
+            return Err(RequestError::BaseUrl(base.to_string()));
+        }
+        Ok(Self {
+            base: format!("{scheme}://{authority}{}", uri.path().trim_end_matches('/')),
+            api: "api".to_string(),
+            public_key,
+        })
+    }
+
+    /// The base URL of this directory, without a trailing slash
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    /// The public key this directory's responses must be signed with
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    /// The [replica](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apireplicareplica_id)
+    /// of another directory with `replica_id`, as listed by [`Directory::replicas`]
+    ///
+    /// The `extensions`, `replicas`, `revoke` and `server-public-key` endpoints aren't mirrored, so don't use those
+    /// calls on a replica. Its responses are still signed by this directory.
+    pub fn replica(&self, replica_id: &str) -> Self {
+        Self {
+            base: self.base.clone(),
+            api: format!("{}/replica/{}", self.api, api::path_segment(replica_id)),
+            public_key: self.public_key,
+        }
+    }
+
+    /// [`GET api/actor/:actor_id`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apiactoractor_id)
+    pub fn actor_info(&self, actor: &ActorId) -> Result<Call<api::ActorInfo>, RequestError> {
+        self.get(
+            &format!("actor/{}", actor.to_path_segment()),
+            Echo::actor(actor),
+        )
+    }
+
+    /// [`GET api/actor/:actor_id/keys`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apiactoractor_idkeys)
+    pub fn actor_keys(&self, actor: &ActorId) -> Result<Call<api::ActorKeys>, RequestError> {
+        self.get(
+            &format!("actor/{}/keys", actor.to_path_segment()),
+            Echo::actor(actor),
+        )
+    }
+
+    /// [`GET api/actor/:actor_id/key/:key_id`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apiactoractor_idkeykey_id)
+    pub fn key_info(
+        &self,
+        actor: &ActorId,
+        key_id: &KeyId,
+    ) -> Result<Call<api::KeyInfo>, RequestError> {
+        self.get(
+            &format!(
+                "actor/{}/key/{}",
+                actor.to_path_segment(),
+                api::path_segment(&key_id.to_string())
+            ),
+            Echo {
+                key_id: Some(key_id.clone()),
+                ..Echo::actor(actor)
+            },
+        )
+    }
+
+    /// [`GET api/actor/:actor_id/auxiliary`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apiactoractor_idauxiliary)
+    pub fn actor_auxiliary(
+        &self,
+        actor: &ActorId,
+    ) -> Result<Call<api::ActorAuxiliary>, RequestError> {
+        self.get(
+            &format!("actor/{}/auxiliary", actor.to_path_segment()),
+            Echo::actor(actor),
+        )
+    }
+
+    /// [`GET api/actor/:actor_id/auxiliary/:aux_data_id`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apiactoractor_idauxiliaryaux_data_id)
+    pub fn aux_info(
+        &self,
+        actor: &ActorId,
+        aux_id: &str,
+    ) -> Result<Call<api::AuxInfo>, RequestError> {
+        self.get(
+            &format!(
+                "actor/{}/auxiliary/{}",
+                actor.to_path_segment(),
+                api::path_segment(aux_id)
+            ),
+            Echo {
+                aux_id: Some(aux_id.to_string()),
+                ..Echo::actor(actor)
+            },
+        )
+    }
+
+    /// [`GET api/history`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apihistory)
+    pub fn history(&self) -> Result<Call<api::History>, RequestError> {
+        self.get("history", Echo::default())
+    }
+
+    /// [`GET api/history/since/:last_hash`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apihistorysincelast_hash)
+    pub fn history_since(
+        &self,
+        last: &MerkleRoot,
+    ) -> Result<Call<api::HistorySince>, RequestError> {
+        self.get(
+            &format!("history/since/{}", api::path_segment(&last.to_string())),
+            Echo::default(),
+        )
+    }
+
+    /// [`GET api/history/view/:hash`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apihistoryviewhash)
+    pub fn history_view(&self, root: &MerkleRoot) -> Result<Call<api::HistoryView>, RequestError> {
+        self.get(
+            &format!("history/view/{}", api::path_segment(&root.to_string())),
+            Echo::default(),
+        )
+    }
+
+    /// [`GET api/extensions`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apiextensions)
+    pub fn extensions(&self) -> Result<Call<api::Extensions>, RequestError> {
+        self.get("extensions", Echo::default())
+    }
+
+    /// [`GET api/replicas`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apireplicas)
+    pub fn replicas(&self) -> Result<Call<api::Replicas>, RequestError> {
+        self.get("replicas", Echo::default())
+    }
+
+    /// [`GET api/server-public-key`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apiserver-public-key)
+    pub fn server_public_key(&self) -> Result<Call<api::ServerPublicKey>, RequestError> {
+        self.get("server-public-key", Echo::default())
+    }
+
+    /// [`POST api/revoke`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#post-apirevoke)
+    /// with `token` at the current time of `clock`
+    ///
+    /// The response is `None` if the directory rejected the token.
+    pub fn revoke(
+        &self,
+        token: RevocationToken,
+        clock: &impl Clock,
+    ) -> Result<Call<Option<api::Revoked>>, RequestError> {
+        let body = json::to_canonical_string(&api::RevokeRequest::new(token, clock))?;
+        let request = self
+            .builder(Method::POST, &self.api_uri("revoke")?)
+            .header(header::CONTENT_TYPE, JSON)
+            .body(body.into_bytes())?;
+        Ok(Call::new(
+            request,
+            Some(self.public_key),
+            Echo::default(),
+            |status, body, _| {
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#post-apirevoke
+                //# If the revocation token is invalid, an HTTP 2204 No Content response will be returned.
+                if status == StatusCode::NO_CONTENT {
+                    return Ok(None);
+                }
+                parse_json(status, body).map(Some)
+            },
+        ))
+    }
+
+    /// Submit a signed protocol message, e.g. an `AddKey`, to the directory's ActivityPub inbox at `inbox`, relative to
+    /// the base URL
+    ///
+    /// `AddKey` and `BurnDown` also need an HTTP Signature from the actor's instance, which the caller's ActivityPub
+    /// stack must add before sending. The inbox isn't part of the JSON REST API, so its response isn't verified.
+    pub fn submit(&self, inbox: &str, message: &ProtocolMessage) -> Result<Call<()>, RequestError> {
+        if message.signature.is_none()
+            && !matches!(message.action, Action::RevokeKeyThirdParty { .. })
+        {
+            return Err(RequestError::Unsigned(message.action_name()));
+        }
+        self.post(inbox, message.to_json()?)
+    }
+
+    /// Submit a protocol message encrypted with [`ProtocolMessage::encrypt`] to the directory's ActivityPub inbox at
+    /// `inbox`, relative to the base URL
+    ///
+    /// Encrypted messages always need an HTTP Signature, which the caller's ActivityPub stack must add before sending.
+    pub fn submit_encrypted(
+        &self,
+        inbox: &str,
+        message: &EncryptedMessage,
+    ) -> Result<Call<()>, RequestError> {
+        self.post(inbox, json::to_canonical_string(message)?)
+    }
+
+    fn get<T: serde::de::DeserializeOwned + Echoes>(
+        &self,
+        path: &str,
+        echo: Echo,
+    ) -> Result<Call<T>, RequestError> {
+        let request = self
+            .builder(Method::GET, &self.api_uri(path)?)
+            .body(Vec::new())?;
+        Ok(Call::new(
+            request,
+            Some(self.public_key),
+            echo,
+            |status, body, echo| {
+                let response = parse_json(status, body)?;
+                echo.check(&response)?;
+                Ok(response)
+            },
+        ))
+    }
+
+    fn post(&self, path: &str, body: String) -> Result<Call<()>, RequestError> {
+        let uri: Uri = format!("{}/{}", self.base, path.trim_start_matches('/')).parse()?;
+        let request = self
+            .builder(Method::POST, &uri)
+            .header(header::CONTENT_TYPE, JSON)
+            .body(body.into_bytes())?;
+        Ok(Call::new(request, None, Echo::default(), |_, _, _| Ok(())))
+    }
+
+    fn api_uri(&self, path: &str) -> Result<Uri, RequestError> {
+        Ok(format!("{}/{}/{path}", self.base, self.api).parse()?)
+    }
+
+    fn builder(&self, method: Method, uri: &Uri) -> http::request::Builder {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::ACCEPT, JSON)
+    }
+}
+
+/// Parse a `200 OK` JSON body, rejecting duplicate keys
+fn parse_json<T: serde::de::DeserializeOwned>(
+    status: StatusCode,
+    body: &[u8],
+) -> Result<T, ResponseError> {
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#json-rest-api
+    //# Response bodies will be JSON (if the status code is 200).
+    if status != StatusCode::OK {
+        return Err(ResponseError::Status(status));
+    }
+    Ok(json::from_str(std::str::from_utf8(body)?)?)
+}
+
+/// The identifiers in a request that its response must echo
+#[derive(Debug, Default, Clone)]
+struct Echo {
+    actor: Option<ActorId>,
+    key_id: Option<KeyId>,
+    aux_id: Option<String>,
+}
+
+impl Echo {
+    fn actor(actor: &ActorId) -> Self {
+        Self {
+            actor: Some(actor.clone()),
+            ..Self::default()
+        }
+    }
+
+    /// Check that `response` is for the requested identifiers, so a signed response for another actor can't be replayed
+    fn check(&self, response: &impl Echoes) -> Result<(), ResponseError> {
+        if self.actor.is_some() && self.actor.as_ref() != response.actor_id() {
+            return Err(ResponseError::Mismatch("actor-id"));
+        }
+        if self.key_id.is_some() && self.key_id.as_ref() != response.key_id() {
+            return Err(ResponseError::Mismatch("key-id"));
+        }
+        if self.aux_id.is_some() && self.aux_id.as_deref() != response.aux_id() {
+            return Err(ResponseError::Mismatch("aux-id"));
+        }
+        Ok(())
+    }
+}
+
+/// The request identifiers a response body echoes
+trait Echoes {
+    fn actor_id(&self) -> Option<&ActorId> {
+        None
+    }
+
+    fn key_id(&self) -> Option<&KeyId> {
+        None
+    }
+
+    fn aux_id(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Echoes for api::ActorInfo {
+    fn actor_id(&self) -> Option<&ActorId> {
+        Some(&self.actor_id)
+    }
+}
+
+impl Echoes for api::ActorKeys {
+    fn actor_id(&self) -> Option<&ActorId> {
+        Some(&self.actor_id)
+    }
+}
+
+impl Echoes for api::KeyInfo {
+    fn actor_id(&self) -> Option<&ActorId> {
+        Some(&self.actor_id)
+    }
+
+    fn key_id(&self) -> Option<&KeyId> {
+        Some(&self.key_id)
+    }
+}
+
+impl Echoes for api::ActorAuxiliary {
+    fn actor_id(&self) -> Option<&ActorId> {
+        Some(&self.actor_id)
+    }
+}
+
+impl Echoes for api::AuxInfo {
+    fn actor_id(&self) -> Option<&ActorId> {
+        Some(&self.actor_id)
+    }
+
+    fn aux_id(&self) -> Option<&str> {
+        Some(&self.aux_id)
+    }
+}
+
+impl Echoes for api::History {}
+impl Echoes for api::HistorySince {}
+impl Echoes for api::HistoryView {}
+impl Echoes for api::Extensions {}
+impl Echoes for api::Replicas {}
+impl Echoes for api::ServerPublicKey {}
+
+/// The delay in a `Retry-After` header, if it's in seconds
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// A request to send to a directory, and how to parse its response into `T`
+#[derive(Debug)]
+pub struct Call<T> {
+    request: Request<Vec<u8>>,
+    parser: Parser<T>,
+}
+
+impl<T> Call<T> {
+    fn new(
+        request: Request<Vec<u8>>,
+        public_key: Option<PublicKey>,
+        echo: Echo,
+        parse: fn(StatusCode, &[u8], &Echo) -> Result<T, ResponseError>,
+    ) -> Self {
+        Self {
+            request,
+            parser: Parser {
+                public_key,
+                echo,
+                parse,
+                marker: PhantomData,
+            },
+        }
+    }
+
+    /// The request to send
+    pub fn request(&self) -> &Request<Vec<u8>> {
+        &self.request
+    }
+
+    /// Parse the response to [`Call::request`], checking its signature against the current time of `clock`
+    pub fn parse<B: AsRef<[u8]>>(
+        &self,
+        response: &Response<B>,
+        clock: &impl Clock,
+    ) -> Result<T, ResponseError> {
+        self.parser.parse(response, clock)
+    }
+
+    /// Split into the request to send and the [`Parser`] for its response
+    pub fn into_parts(self) -> (Request<Vec<u8>>, Parser<T>) {
+        (self.request, self.parser)
+    }
+}
+
+/// Parses the response to a [`Call`]
+#[derive(Debug)]
+pub struct Parser<T> {
+    public_key: Option<PublicKey>,
+    echo: Echo,
+    parse: fn(StatusCode, &[u8], &Echo) -> Result<T, ResponseError>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Parser<T> {
+    /// Parse `response` into `T`, checking its signature against the current time of `clock`
+    ///
+    /// Apart from a `429 Too Many Requests`, the response's signature is verified before its status is trusted, so an
+    /// injected `404 Not Found` can't pass for a takedown. Error statuses are then mapped to [`ResponseError`]s without
+    /// looking at the body.
+    pub fn parse<B: AsRef<[u8]>>(
+        &self,
+        response: &Response<B>,
+        clock: &impl Clock,
+    ) -> Result<T, ResponseError> {
+        let status = response.status();
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#rate-limiting-bad-requests
+        //# If the client attempts to make a request before this time has elapsed, the request is rejected
+        //# and the server responds with an HTTP 429 error.
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(ResponseError::RateLimited {
+                retry_after: retry_after(response.headers()),
+            });
+        }
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#json-rest-api
+        //# Every HTTP response will include a signature over the HTTP response body, which will be sent as an additional HTTP
+        //# header, adhering to [RFC 9421 with EdDSA over edwards25519](https://www.rfc-editor.org/rfc/rfc9421.html#name-eddsa-using-curve-edwards25).
+        if let Some(public_key) = &self.public_key {
+            verify_response(public_key, response, clock)?;
+        }
+        if status == StatusCode::NOT_FOUND {
+            return Err(ResponseError::NotFound);
+        }
+        if !status.is_success() {
+            return Err(ResponseError::Status(status));
+        }
+        (self.parse)(status, response.body().as_ref(), &self.echo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pkd_core::{
+        ContextValue as _, SigningKey, Timestamp, Timestamped,
+        action::{AddOrRevokeKey, AddOrRevokeKeyInner, Encrypt, PlainText},
+    };
+
+    use super::*;
+    use crate::signature::testing::{NOW, alice, key, sign};
+
+    const ROOT: MerkleRoot = MerkleRoot::new([7; 32]);
+
+    fn directory(base: &str) -> Result<Directory, RequestError> {
+        Directory::new(base, key().public_key())
+    }
+
+    fn ok(body: &str) -> Response<&[u8]> {
+        let mut response = Response::new(body.as_bytes());
+        sign(&mut response, &key());
+        response
+    }
+
+    fn status(status: StatusCode) -> Response<&'static [u8]> {
+        let mut response = Response::builder().status(status).body(&[][..]).unwrap();
+        sign(&mut response, &key());
+        response
+    }
+
+    #[test]
+    fn base_url() {
+        for (base, expected) in [
+            ("https://pkd.example.com", "https://pkd.example.com"),
+            ("https://pkd.example.com/", "https://pkd.example.com"),
+            ("http://localhost:8080/pkd/", "http://localhost:8080/pkd"),
+        ] {
+            assert_eq!(directory(base).unwrap().base(), expected);
+        }
+        for base in [
+            "pkd.example.com",
+            "/api",
+            "ftp://pkd.example.com",
+            "https://pkd.example.com/?q",
+        ] {
+            assert!(directory(base).is_err(), "{base}");
+        }
+    }
+
+    #[test]
+    fn requests() {
+        let directory = directory("https://pkd.example.com/").unwrap();
+        let actor = "https%3A%2F%2Fexample.com%2Fusers%2Falice";
+        let key_id = KeyId::generate().unwrap();
+        let uris = [
+            directory
+                .actor_info(&alice())
+                .unwrap()
+                .request()
+                .uri()
+                .clone(),
+            directory
+                .actor_keys(&alice())
+                .unwrap()
+                .request()
+                .uri()
+                .clone(),
+            directory
+                .key_info(&alice(), &key_id)
+                .unwrap()
+                .request()
+                .uri()
+                .clone(),
+            directory
+                .actor_auxiliary(&alice())
+                .unwrap()
+                .request()
+                .uri()
+                .clone(),
+            directory
+                .aux_info(&alice(), "a/b")
+                .unwrap()
+                .request()
+                .uri()
+                .clone(),
+            directory.history().unwrap().request().uri().clone(),
+            directory
+                .history_since(&ROOT)
+                .unwrap()
+                .request()
+                .uri()
+                .clone(),
+            directory
+                .history_view(&ROOT)
+                .unwrap()
+                .request()
+                .uri()
+                .clone(),
+            directory.extensions().unwrap().request().uri().clone(),
+            directory.replicas().unwrap().request().uri().clone(),
+            directory
+                .server_public_key()
+                .unwrap()
+                .request()
+                .uri()
+                .clone(),
+        ];
+        let expected = [
+            format!("/api/actor/{actor}"),
+            format!("/api/actor/{actor}/keys"),
+            format!("/api/actor/{actor}/key/{key_id}"),
+            format!("/api/actor/{actor}/auxiliary"),
+            format!("/api/actor/{actor}/auxiliary/a%2Fb"),
+            "/api/history".to_string(),
+            format!("/api/history/since/pkd-mr-v1%3A{}", &ROOT.to_string()[10..]),
+            format!("/api/history/view/pkd-mr-v1%3A{}", &ROOT.to_string()[10..]),
+            "/api/extensions".to_string(),
+            "/api/replicas".to_string(),
+            "/api/server-public-key".to_string(),
+        ];
+        for (uri, expected) in uris.iter().zip(expected) {
+            assert_eq!(uri.host(), Some("pkd.example.com"));
+            assert_eq!(uri.path(), expected);
+        }
+
+        let call = directory
+            .replica("7k18At1PNkUmWokYbkpS5t29ZPWQASvg2dWXaFiOnac")
+            .history()
+            .unwrap();
+        assert_eq!(call.request().method(), Method::GET);
+        assert_eq!(call.request().headers()[header::ACCEPT], JSON);
+        assert_eq!(
+            call.request().uri().path(),
+            "/api/replica/7k18At1PNkUmWokYbkpS5t29ZPWQASvg2dWXaFiOnac/history"
+        );
+    }
+
+    #[test]
+    fn responses() {
+        let call = directory("https://pkd.example.com")
+            .unwrap()
+            .actor_info(&alice())
+            .unwrap();
+        let info = call
+            .parse(&ok(
+                r#"{"!pkd-context":"fedi-e2ee:v1/api/actor/info","actor-id":"https://example.com/users/alice","count-aux":0,"count-keys":1}"#,
+            ), &NOW)
+            .unwrap();
+        assert_eq!((info.actor_id, info.count_keys), (alice(), 1));
+
+        // A response from another endpoint is rejected
+        let err = call
+            .parse(&ok(r#"{"!pkd-context":"fedi-e2ee:v1/api/actor/get-keys","actor-id":"https://example.com/users/alice","public-keys":[]}"#), &NOW)
+            .unwrap_err();
+        assert!(matches!(err, ResponseError::Json(_)), "{err:?}");
+        // So are duplicate keys
+        let err = call
+            .parse(&ok(r#"{"!pkd-context":"fedi-e2ee:v1/api/actor/info","actor-id":"https://example.com/users/alice","count-aux":0,"count-keys":1,"count-keys":2}"#), &NOW)
+            .unwrap_err();
+        assert!(matches!(err, ResponseError::Json(_)), "{err:?}");
+
+        // The signature is checked before the body is parsed
+        let err = call.parse(&Response::new("not json"), &NOW).unwrap_err();
+        assert!(
+            matches!(err, ResponseError::Signature(HttpSignatureError::Missing)),
+            "{err:?}"
+        );
+        let mut forged = Response::new("not json");
+        sign(&mut forged, &SigningKey::from_bytes(&[8; 32]));
+        let err = call.parse(&forged, &NOW).unwrap_err();
+        assert!(
+            matches!(err, ResponseError::Signature(HttpSignatureError::Invalid)),
+            "{err:?}"
+        );
+
+        assert!(matches!(
+            call.parse(&status(StatusCode::NOT_FOUND), &NOW),
+            Err(ResponseError::NotFound)
+        ));
+        // Error statuses must be signed too, so an injected 404 can't fake a takedown
+        for code in [StatusCode::NOT_FOUND, StatusCode::INTERNAL_SERVER_ERROR] {
+            let unsigned = Response::builder().status(code).body(&[][..]).unwrap();
+            let err = call.parse(&unsigned, &NOW).unwrap_err();
+            assert!(
+                matches!(err, ResponseError::Signature(HttpSignatureError::Missing)),
+                "{err:?}"
+            );
+        }
+        assert!(matches!(
+            call.parse(&status(StatusCode::NO_CONTENT), &NOW),
+            Err(ResponseError::Status(StatusCode::NO_CONTENT))
+        ));
+        assert!(matches!(
+            call.parse(&status(StatusCode::INTERNAL_SERVER_ERROR), &NOW),
+            Err(ResponseError::Status(StatusCode::INTERNAL_SERVER_ERROR))
+        ));
+        assert!(matches!(
+            call.parse(&status(StatusCode::TOO_MANY_REQUESTS), &NOW),
+            Err(ResponseError::RateLimited { retry_after: None })
+        ));
+        let limited = Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header(header::RETRY_AFTER, "30")
+            .body(&[][..])
+            .unwrap();
+        let (_, parser) = call.into_parts();
+        assert!(matches!(
+            parser.parse(&limited, &NOW),
+            Err(ResponseError::RateLimited { retry_after: Some(d) }) if d == Duration::from_secs(30)
+        ));
+    }
+
+    #[test]
+    fn echoes() {
+        let directory = directory("https://pkd.example.com").unwrap();
+        let keys = |actor: &str| {
+            format!(
+                r#"{{"!pkd-context":"fedi-e2ee:v1/api/actor/get-keys","actor-id":"{actor}","public-keys":[]}}"#
+            )
+        };
+        let call = directory.actor_keys(&alice()).unwrap();
+        call.parse(&ok(&keys("https://EXAMPLE.com/users/alice")), &NOW)
+            .unwrap();
+        // Bob's signed response can't be replayed for a request for Alice
+        let err = call
+            .parse(&ok(&keys("https://example.com/users/bob")), &NOW)
+            .unwrap_err();
+        assert!(
+            matches!(err, ResponseError::Mismatch("actor-id")),
+            "{err:?}"
+        );
+
+        let key_info = r#"{"!pkd-context":"fedi-e2ee:v1/api/actor/key-info","actor-id":"https://example.com/users/alice","created":"1722176511","inclusion-proof":[],"key-id":"foo","merkle-root":"pkd-mr-v1:rZgQvJn16wkOuNq3ejHqC0zDkuQ-3GBpCR0YP6Xy5yQ","public-key":"ed25519:Tm2XBvb0mAb4ldVubCzvz0HMTczR8VGF44sv478VFLM","revoked":null,"revoke-root":null}"#;
+        let call = directory
+            .key_info(&alice(), &"foo".parse().unwrap())
+            .unwrap();
+        call.parse(&ok(key_info), &NOW).unwrap();
+        let call = directory
+            .key_info(&alice(), &"Zm9v".parse().unwrap())
+            .unwrap();
+        let err = call.parse(&ok(key_info), &NOW).unwrap_err();
+        assert!(matches!(err, ResponseError::Mismatch("key-id")), "{err:?}");
+
+        let aux_info = r#"{"!pkd-context":"fedi-e2ee:v1/api/actor/get-aux","actor-id":"https://example.com/users/alice","aux-data":"age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p","aux-id":"XUUDSZSwIWsanCX9Dr4WH5g9p1_pTaK6hZymeISJI0A","aux-type":"age-v1","created":"1730902834","inclusion-proof":[],"merkle-root":"pkd-mr-v1:KOspo1eBvXE9ZPyyNmW1sqqFeLqLA5f1LBCYHct1n9c","revoked":null,"revoke-root":null}"#;
+        let call = directory
+            .aux_info(&alice(), "XUUDSZSwIWsanCX9Dr4WH5g9p1_pTaK6hZymeISJI0A")
+            .unwrap();
+        call.parse(&ok(aux_info), &NOW).unwrap();
+        let call = directory
+            .aux_info(&alice(), "qVca3ELZdRW2yGZj8kfbynFKPDrOCoKzFJlqwquzfDw")
+            .unwrap();
+        let err = call.parse(&ok(aux_info), &NOW).unwrap_err();
+        assert!(matches!(err, ResponseError::Mismatch("aux-id")), "{err:?}");
+    }
+
+    #[test]
+    fn revoke() {
+        let token = RevocationToken::issue(&SigningKey::from_bytes(&[1; 32]));
+        let call = directory("https://pkd.example.com")
+            .unwrap()
+            .revoke(token.clone(), &Timestamp::from_secs(1730909831))
+            .unwrap();
+        let request = call.request();
+        assert_eq!(request.method(), Method::POST);
+        assert_eq!(request.uri().path(), "/api/revoke");
+        assert_eq!(request.headers()[header::CONTENT_TYPE], JSON);
+        let body: api::RevokeRequest =
+            json::from_str(std::str::from_utf8(request.body()).unwrap()).unwrap();
+        assert_eq!(body.revocation_token, token);
+        assert_eq!(body.current_time, Timestamp::from_secs(1730909831));
+
+        let revoked = call
+            .parse(
+                &ok(r#"{"!pkd-context":"fedi-e2ee:v1/api/revoke","time":"1730909831"}"#),
+                &NOW,
+            )
+            .unwrap();
+        assert!(revoked.is_some());
+        assert_eq!(
+            call.parse(&status(StatusCode::NO_CONTENT), &NOW).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn submit() {
+        let directory = directory("https://pkd.example.com").unwrap();
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let (cipher, symmetric_keys) = AddOrRevokeKeyInner::<PlainText> {
+            actor: alice(),
+            public_key: key.public_key(),
+        }
+        .encrypt(&ROOT)
+        .unwrap();
+        let mut message = ProtocolMessage::new(
+            Action::AddKey(AddOrRevokeKey {
+                message: Timestamped::new(Timestamp::from_secs(1730909831), cipher),
+                symmetric_keys,
+            }),
+            ROOT,
+        );
+        assert!(matches!(
+            directory.submit("inbox", &message),
+            Err(RequestError::Unsigned("AddKey"))
+        ));
+
+        message.sign(&key).unwrap();
+        let call = directory.submit("/users/pkd/inbox", &message).unwrap();
+        let request = call.request();
+        assert_eq!(request.method(), Method::POST);
+        assert_eq!(request.uri(), "https://pkd.example.com/users/pkd/inbox");
+        assert_eq!(request.body(), message.to_json().unwrap().as_bytes());
+        // The inbox isn't part of the JSON REST API, so its responses aren't signed
+        call.parse(
+            &Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .body("")
+                .unwrap(),
+            &NOW,
+        )
+        .unwrap();
+        assert!(matches!(
+            call.parse(&status(StatusCode::BAD_REQUEST), &NOW),
+            Err(ResponseError::Status(StatusCode::BAD_REQUEST))
+        ));
+
+        let encrypted = pkd_core::EncryptedMessage {
+            context: Default::default(),
+            encrypted_message: vec![1, 2, 3],
+        };
+        let call = directory.submit_encrypted("inbox", &encrypted).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(call.request().body()).unwrap();
+        assert_eq!(body["!pkd-context"], pkd_core::ProtocolContextTag::CONTEXT);
+        assert_eq!(body["encrypted-message"], "AQID");
+    }
+}
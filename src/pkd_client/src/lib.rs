@@ -1 +1,21 @@
+//! Client for a `pkd` Public Key Directory
+//!
+//! [`Directory`] turns high-level calls into [`http::Request`]s and parses the matching [`http::Response`]s, without
+//! doing any IO itself. Send the requests with whichever HTTP stack you already use. Every response must carry an
+//! [RFC 9421](https://www.rfc-editor.org/rfc/rfc9421.html) Ed25519 signature from the directory's pinned public key.
+//!
+//! With the `reqwest` feature, `PkdClient` sends them for you.
+
+#![deny(missing_docs)]
+#![deny(unsafe_code)]
+
+#[cfg(feature = "reqwest")]
+mod client;
+mod directory;
+mod signature;
+
+#[cfg(feature = "reqwest")]
+pub use client::*;
+pub use directory::*;
 pub use pkd_core;
+pub use signature::*;
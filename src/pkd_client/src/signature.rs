@@ -0,0 +1,530 @@
+//! [RFC 9421](https://www.rfc-editor.org/rfc/rfc9421.html) HTTP Message Signatures on directory responses
+//!
+//! Only Ed25519 is accepted. The signature must cover the `Content-Digest`
+//! ([RFC 9530](https://www.rfc-editor.org/rfc/rfc9530.html)) of the body, which must match, and be
+//! [`created`](https://www.rfc-editor.org/rfc/rfc9421.html#section-2.3) within [`SIGNATURE_WINDOW`] of the current time.
+
+use std::time::Duration;
+
+use http::{HeaderMap, Response};
+use pkd_core::{Clock, PublicKey, Signature, Timestamp};
+use sfv::{BareItem, Dictionary, FieldType, InnerList, List, ListEntry, Parser};
+use sha2::{Digest, Sha256, Sha512};
+
+const SIGNATURE: &str = "signature";
+const SIGNATURE_INPUT: &str = "signature-input";
+const CONTENT_DIGEST: &str = "content-digest";
+
+/// The only `alg` accepted from RFC 9421
+const ED25519: &str = "ed25519";
+
+/// How far a signature's `created` time may be from the current time, allowing for clock skew
+///
+/// Older signatures are rejected so a captured response, e.g. a key list from before a revocation, can't be replayed.
+pub const SIGNATURE_WINDOW: Duration = Duration::from_secs(300);
+
+/// Errors from verifying the HTTP Message Signature on a response
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum HttpSignatureError {
+    /// The response has no `Signature` or `Signature-Input`
+    #[error("response isn't signed")]
+    Missing,
+    /// A header isn't a valid structured field
+    #[error("malformed {0} header")]
+    Malformed(&'static str),
+    /// A signature uses an algorithm other than Ed25519, e.g. HMAC, RSA, ECDSA or JWS
+    #[error("unsupported signature algorithm {0}")]
+    Algorithm(String),
+    /// No signature covers the `Content-Digest`, so the body isn't signed
+    #[error("signature doesn't cover the response body")]
+    Uncovered,
+    /// A covered component is unsupported, duplicated or missing from the response
+    #[error("unsupported or missing component {0}")]
+    Component(String),
+    /// The `Content-Digest` has no supported digest, or doesn't match the body
+    #[error("content digest doesn't match the body")]
+    Digest,
+    /// The signature's `expires` has passed
+    #[error("signature expired")]
+    Expired,
+    /// The signature has no `created` time, or it isn't within [`SIGNATURE_WINDOW`] of the current time
+    #[error("signature isn't recent")]
+    Stale,
+    /// No signature is valid for the directory's public key
+    #[error("invalid signature")]
+    Invalid,
+}
+
+/// Verify that `response` was signed by the directory with `key`, before its body is trusted.
+///
+/// Every signature in `Signature-Input` must be Ed25519, cover the `Content-Digest` and be recent according to `clock`,
+/// and at least one must verify.
+pub fn verify_response<B: AsRef<[u8]>>(
+    key: &PublicKey,
+    response: &Response<B>,
+    clock: &impl Clock,
+) -> Result<(), HttpSignatureError> {
+    let headers = response.headers();
+    let inputs = dictionary(headers, SIGNATURE_INPUT)?.ok_or(HttpSignatureError::Missing)?;
+    let signatures = dictionary(headers, SIGNATURE)?.ok_or(HttpSignatureError::Missing)?;
+    if inputs.is_empty() {
+        return Err(HttpSignatureError::Missing);
+    }
+
+    // Check every signature's parameters first, so a forbidden algorithm is never silently skipped
+    let mut checked = Vec::with_capacity(inputs.len());
+    for (label, input) in &inputs {
+        let ListEntry::InnerList(input) = input else {
+            return Err(HttpSignatureError::Malformed(SIGNATURE_INPUT));
+        };
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#json-rest-api
+        //# Public Key Directory software **MUST NOT** support the HMAC, RSA, ECDSA, or JWS signature algorithms from RFC 9421.
+        if let Some(alg) = input.params.get("alg") {
+            match alg.as_string() {
+                Some(alg) if alg.as_str() == ED25519 => {}
+                _ => return Err(HttpSignatureError::Algorithm(display(alg))),
+            }
+        }
+        let now = clock.now();
+        let created = timestamp(input, "created")?.ok_or(HttpSignatureError::Stale)?;
+        if !created.is_within(SIGNATURE_WINDOW, now) {
+            return Err(HttpSignatureError::Stale);
+        }
+        if timestamp(input, "expires")?.is_some_and(|expires| expires < now) {
+            return Err(HttpSignatureError::Expired);
+        }
+        if !input
+            .items
+            .iter()
+            .any(|item| item.bare_item.as_string().map(|s| s.as_str()) == Some(CONTENT_DIGEST))
+        {
+            return Err(HttpSignatureError::Uncovered);
+        }
+        let Some(ListEntry::Item(signature)) = signatures.get(label) else {
+            return Err(HttpSignatureError::Missing);
+        };
+        let signature = signature
+            .bare_item
+            .as_byte_sequence()
+            .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+            .ok_or(HttpSignatureError::Malformed(SIGNATURE))?;
+        checked.push((signature_base(response, input)?, Signature(signature)));
+    }
+
+    verify_digest(headers, response.body().as_ref())?;
+    checked
+        .iter()
+        .any(|(base, signature)| key.verify(base.as_bytes(), signature).is_ok())
+        .then_some(())
+        .ok_or(HttpSignatureError::Invalid)
+}
+
+/// Parse the integer signature parameter `name` as a [`Timestamp`], if it's present
+fn timestamp(input: &InnerList, name: &str) -> Result<Option<Timestamp>, HttpSignatureError> {
+    let Some(value) = input.params.get(name) else {
+        return Ok(None);
+    };
+    value
+        .as_integer()
+        .and_then(|value| u64::try_from(value).ok())
+        .map(|value| Some(Timestamp::from_secs(value)))
+        .ok_or(HttpSignatureError::Malformed(SIGNATURE_INPUT))
+}
+
+/// Parse the structured field dictionary in header `name`, if it's present
+fn dictionary(
+    headers: &HeaderMap,
+    name: &'static str,
+) -> Result<Option<Dictionary>, HttpSignatureError> {
+    let Some(value) = combined(headers, name) else {
+        return Ok(None);
+    };
+    Parser::new(&value)
+        .parse()
+        .map(Some)
+        .map_err(|_| HttpSignatureError::Malformed(name))
+}
+
+/// The values of header `name`, combined as in [RFC 9110](https://www.rfc-editor.org/rfc/rfc9110.html#section-5.3)
+fn combined(headers: &HeaderMap, name: &str) -> Option<String> {
+    let values = headers
+        .get_all(name)
+        .iter()
+        .map(|value| value.to_str().map(str::trim))
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    (!values.is_empty()).then(|| values.join(", "))
+}
+
+/// Build the [signature base](https://www.rfc-editor.org/rfc/rfc9421.html#name-creating-the-signature-base)
+/// of `response` for the covered components and parameters in `input`
+pub(crate) fn signature_base<B>(
+    response: &Response<B>,
+    input: &InnerList,
+) -> Result<String, HttpSignatureError> {
+    let mut base = String::new();
+    let mut seen = Vec::with_capacity(input.items.len());
+    for item in &input.items {
+        let name = item
+            .bare_item
+            .as_string()
+            .map(|name| name.as_str())
+            .ok_or(HttpSignatureError::Malformed(SIGNATURE_INPUT))?;
+        // Component parameters such as `sf`, `bs` and `req` aren't needed for a response signature
+        if !item.params.is_empty() || seen.contains(&name) {
+            return Err(HttpSignatureError::Component(name.to_string()));
+        }
+        seen.push(name);
+        let value = match name {
+            "@status" => response.status().as_str().to_string(),
+            _ if name.starts_with('@') || name.bytes().any(|b| b.is_ascii_uppercase()) => {
+                return Err(HttpSignatureError::Component(name.to_string()));
+            }
+            _ => combined(response.headers(), name)
+                .ok_or_else(|| HttpSignatureError::Component(name.to_string()))?,
+        };
+        base.push_str(&format!("\"{name}\": {value}\n"));
+    }
+    let params = List::from([ListEntry::InnerList(input.clone())])
+        .serialize()
+        .ok_or(HttpSignatureError::Malformed(SIGNATURE_INPUT))?;
+    base.push_str(&format!("\"@signature-params\": {params}"));
+    Ok(base)
+}
+
+/// Check the `Content-Digest` of `body`
+fn verify_digest(headers: &HeaderMap, body: &[u8]) -> Result<(), HttpSignatureError> {
+    let digests = dictionary(headers, CONTENT_DIGEST)?.ok_or(HttpSignatureError::Digest)?;
+    let mut verified = false;
+    for (algorithm, digest) in &digests {
+        let expected = match algorithm.as_str() {
+            "sha-256" => Sha256::digest(body).to_vec(),
+            "sha-512" => Sha512::digest(body).to_vec(),
+            _ => continue,
+        };
+        let ListEntry::Item(digest) = digest else {
+            return Err(HttpSignatureError::Malformed(CONTENT_DIGEST));
+        };
+        if digest.bare_item.as_byte_sequence() != Some(expected.as_slice()) {
+            return Err(HttpSignatureError::Digest);
+        }
+        verified = true;
+    }
+    verified.then_some(()).ok_or(HttpSignatureError::Digest)
+}
+
+/// Show a parameter value for errors
+fn display(value: &BareItem) -> String {
+    match value.as_string() {
+        Some(value) => value.as_str().to_string(),
+        None => format!("{value:?}"),
+    }
+}
+
+/// Fixtures shared by this crate's tests
+#[cfg(test)]
+pub(crate) mod testing {
+    use http::HeaderValue;
+    use pkd_core::{SigningKey, action::ActorId};
+    use sfv::{DictSerializer, KeyRef};
+
+    use super::*;
+
+    /// The directory's signing key
+    pub(crate) fn key() -> SigningKey {
+        SigningKey::from_bytes(&[9; 32])
+    }
+
+    pub(crate) fn alice() -> ActorId {
+        "https://example.com/users/alice".parse().unwrap()
+    }
+
+    /// The time responses are signed at, and the clock they're verified with
+    pub(crate) const NOW: Timestamp = Timestamp::from_secs(1730909831);
+
+    /// Sign `response` with `key` at [`NOW`] as a directory would
+    pub(crate) fn sign<B: AsRef<[u8]>>(response: &mut Response<B>, key: &SigningKey) {
+        sign_at(response, key, NOW);
+    }
+
+    /// Sign `response` with `key` at `created` as a directory would
+    pub(crate) fn sign_at<B: AsRef<[u8]>>(
+        response: &mut Response<B>,
+        key: &SigningKey,
+        created: Timestamp,
+    ) {
+        let mut digest = DictSerializer::new();
+        digest.bare_item(
+            KeyRef::from_str("sha-256").unwrap(),
+            Sha256::digest(response.body().as_ref()).as_slice(),
+        );
+        let digest = digest.finish().unwrap();
+        response
+            .headers_mut()
+            .insert(CONTENT_DIGEST, HeaderValue::from_str(&digest).unwrap());
+
+        sign_with(
+            response,
+            key,
+            &format!(
+                r#"sig1=("@status" "content-digest");created={created};keyid="pkd";alg="ed25519""#
+            ),
+        );
+    }
+
+    /// Replace the `sig1` signature on `response` with one over `input`
+    pub(crate) fn sign_with<B>(response: &mut Response<B>, key: &SigningKey, input: &str) {
+        let parsed = match Parser::new(input)
+            .parse::<Dictionary>()
+            .unwrap()
+            .swap_remove("sig1")
+        {
+            Some(ListEntry::InnerList(list)) => list,
+            _ => unreachable!(),
+        };
+        let signature = key.sign(signature_base(response, &parsed).unwrap().as_bytes());
+
+        let mut header = DictSerializer::new();
+        header.bare_item(KeyRef::from_str("sig1").unwrap(), signature.0.as_slice());
+        let headers = response.headers_mut();
+        headers.insert(SIGNATURE_INPUT, HeaderValue::from_str(input).unwrap());
+        headers.insert(
+            SIGNATURE,
+            HeaderValue::from_str(&header.finish().unwrap()).unwrap(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{HeaderValue, StatusCode};
+    use pkd_core::SigningKey;
+
+    use super::{
+        testing::{NOW, key, sign, sign_with},
+        *,
+    };
+
+    const BODY: &str = r#"{"!pkd-context":"fedi-e2ee:v1/api/revoke","time":"1730909831"}"#;
+
+    fn signed() -> Response<&'static str> {
+        let mut response = Response::new(BODY);
+        sign(&mut response, &key());
+        response
+    }
+
+    /// Replace header `name` of a signed response
+    fn with(name: &'static str, value: &str) -> Response<&'static str> {
+        let mut response = signed();
+        response
+            .headers_mut()
+            .insert(name, HeaderValue::from_str(value).unwrap());
+        response
+    }
+
+    /// Re-sign a response with a different `Signature-Input`
+    fn with_input(input: &str) -> Response<&'static str> {
+        let mut response = signed();
+        sign_with(&mut response, &key(), input);
+        response
+    }
+
+    #[test]
+    fn signature_base_format() {
+        // https://www.rfc-editor.org/rfc/rfc9421.html#name-creating-the-signature-base
+        let response = signed();
+        let input = match Parser::new(&combined(response.headers(), SIGNATURE_INPUT).unwrap())
+            .parse::<Dictionary>()
+            .unwrap()
+            .swap_remove("sig1")
+        {
+            Some(ListEntry::InnerList(list)) => list,
+            _ => unreachable!(),
+        };
+        let digest = combined(response.headers(), CONTENT_DIGEST).unwrap();
+        assert_eq!(
+            signature_base(&response, &input).unwrap(),
+            format!(
+                "\"@status\": 200\n\"content-digest\": {digest}\n\"@signature-params\": (\"@status\" \"content-digest\");created=1730909831;keyid=\"pkd\";alg=\"ed25519\""
+            )
+        );
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#json-rest-api
+    //= type=test
+    //# Every HTTP response will include a signature over the HTTP response body, which will be sent as an additional HTTP
+    //# header, adhering to [RFC 9421 with EdDSA over edwards25519](https://www.rfc-editor.org/rfc/rfc9421.html#name-eddsa-using-curve-edwards25).
+    #[test]
+    fn verify() {
+        verify_response(&key().public_key(), &signed(), &NOW).unwrap();
+        // `alg` is optional, the pinned key determines the algorithm
+        verify_response(
+            &key().public_key(),
+            &with_input(r#"sig1=("content-digest");created=1730909831"#),
+            &NOW,
+        )
+        .unwrap();
+
+        let other = SigningKey::from_bytes(&[8; 32]).public_key();
+        assert_eq!(
+            verify_response(&other, &signed(), &NOW),
+            Err(HttpSignatureError::Invalid)
+        );
+
+        let mut tampered = signed();
+        *tampered.body_mut() = r#"{"!pkd-context":"fedi-e2ee:v1/api/revoke","time":"1730909832"}"#;
+        assert_eq!(
+            verify_response(&key().public_key(), &tampered, &NOW),
+            Err(HttpSignatureError::Digest)
+        );
+
+        let mut status = signed();
+        *status.status_mut() = StatusCode::CREATED;
+        assert_eq!(
+            verify_response(&key().public_key(), &status, &NOW),
+            Err(HttpSignatureError::Invalid)
+        );
+
+        assert_eq!(
+            verify_response(&key().public_key(), &Response::new(BODY), &NOW),
+            Err(HttpSignatureError::Missing)
+        );
+        assert_eq!(
+            verify_response(&key().public_key(), &with(SIGNATURE, "sig1=:AAAA:"), &NOW),
+            Err(HttpSignatureError::Malformed(SIGNATURE))
+        );
+        assert_eq!(
+            verify_response(&key().public_key(), &with(SIGNATURE, "sig2=:AAAA:"), &NOW),
+            Err(HttpSignatureError::Missing)
+        );
+        assert_eq!(
+            verify_response(
+                &key().public_key(),
+                &with(CONTENT_DIGEST, "md5=:AAAA:"),
+                &NOW
+            ),
+            Err(HttpSignatureError::Digest)
+        );
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#json-rest-api
+    //= type=test
+    //# Public Key Directory software **MUST NOT** support the HMAC, RSA, ECDSA, or JWS signature algorithms from RFC 9421.
+    #[test]
+    fn algorithms() {
+        for alg in [
+            "hmac-sha256",
+            "rsa-pss-sha512",
+            "rsa-v1_5-sha256",
+            "ecdsa-p256-sha256",
+            "ecdsa-p384-sha384",
+            "EdDSA",
+            "ES256",
+        ] {
+            assert_eq!(
+                verify_response(
+                    &key().public_key(),
+                    &with_input(&format!(r#"sig1=("content-digest");alg="{alg}""#)),
+                    &NOW
+                ),
+                Err(HttpSignatureError::Algorithm(alg.to_string()))
+            );
+        }
+        // A forbidden algorithm is rejected even alongside a valid Ed25519 signature
+        let mut response = signed();
+        let input = combined(response.headers(), SIGNATURE_INPUT).unwrap();
+        response.headers_mut().insert(
+            SIGNATURE_INPUT,
+            HeaderValue::from_str(&format!(
+                r#"{input}, sig2=("content-digest");alg="hmac-sha256""#
+            ))
+            .unwrap(),
+        );
+        assert_eq!(
+            verify_response(&key().public_key(), &response, &NOW),
+            Err(HttpSignatureError::Algorithm("hmac-sha256".to_string()))
+        );
+    }
+
+    #[test]
+    fn components() {
+        assert_eq!(
+            verify_response(
+                &key().public_key(),
+                &with_input(r#"sig1=("@status");created=1730909831"#),
+                &NOW
+            ),
+            Err(HttpSignatureError::Uncovered)
+        );
+        for component in [
+            r#""@method""#,
+            r#""content-digest";sf"#,
+            r#""Content-Type""#,
+            r#""x-missing""#,
+            r#""@status""#,
+        ] {
+            let input =
+                format!(r#"sig1=("content-digest" "@status" {component});created=1730909831"#);
+            let mut response = signed();
+            response
+                .headers_mut()
+                .insert(SIGNATURE_INPUT, HeaderValue::from_str(&input).unwrap());
+            assert!(
+                matches!(
+                    verify_response(&key().public_key(), &response, &NOW),
+                    Err(HttpSignatureError::Component(_))
+                ),
+                "{component}"
+            );
+        }
+        assert_eq!(
+            verify_response(
+                &key().public_key(),
+                &with_input(r#"sig1=("content-digest");created=1730909831;expires=1730909830"#),
+                &NOW
+            ),
+            Err(HttpSignatureError::Expired)
+        );
+        verify_response(
+            &key().public_key(),
+            &with_input(r#"sig1=("content-digest");created=1730909831;expires=1730909832"#),
+            &NOW,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn created() {
+        let window = SIGNATURE_WINDOW.as_secs();
+        let at = |secs: u64| Timestamp::from_secs(secs);
+
+        // Within the window on either side, allowing for clock skew
+        for now in [NOW.as_secs() - window, NOW.as_secs() + window] {
+            verify_response(&key().public_key(), &signed(), &at(now)).unwrap();
+        }
+        // A replayed response is rejected once the window has passed, as is one from the future
+        for now in [NOW.as_secs() - window - 1, NOW.as_secs() + window + 1] {
+            assert_eq!(
+                verify_response(&key().public_key(), &signed(), &at(now)),
+                Err(HttpSignatureError::Stale)
+            );
+        }
+
+        assert_eq!(
+            verify_response(
+                &key().public_key(),
+                &with_input(r#"sig1=("content-digest")"#),
+                &NOW
+            ),
+            Err(HttpSignatureError::Stale)
+        );
+        assert_eq!(
+            verify_response(
+                &key().public_key(),
+                &with_input(r#"sig1=("content-digest");created="1730909831""#),
+                &NOW
+            ),
+            Err(HttpSignatureError::Malformed(SIGNATURE_INPUT))
+        );
+    }
+}
@@ -3,17 +3,60 @@
 //!
 
 use crate::{
-    MerkleRoot, PublicKey,
+    CryptoError, MerkleRoot, PublicKey,
     utils::{Encrypted, Timestamped, sealed::Sealed},
 };
 
+/// Implements [`Encrypt`] and [`Decrypt`] for a struct polymorphic over [`Wrap`]
+///
+/// Each field is listed alongside the attribute name it's encrypted as.
+macro_rules! impl_encrypt {
+    ($ty:ident { $($field:ident => $attr:literal),+ $(,)? }) => {
+        impl $crate::action::Encrypt for $ty<$crate::action::PlainText> {
+            type CipherText = $ty<$crate::action::CipherText>;
+            type Keys = $ty<$crate::action::SymmetricKey>;
+
+            fn encrypt(
+                &self,
+                root: &$crate::MerkleRoot,
+            ) -> Result<(Self::CipherText, Self::Keys), $crate::CryptoError> {
+                use $crate::crypto::AttributeField;
+
+                $(let $field = self.$field.encrypt_field($attr, root)?;)+
+                Ok(($ty { $($field: $field.0),+ }, $ty { $($field: $field.1),+ }))
+            }
+        }
+
+        impl $crate::action::Decrypt for $ty<$crate::action::CipherText> {
+            type PlainText = $ty<$crate::action::PlainText>;
+            type Keys = $ty<$crate::action::SymmetricKey>;
+
+            fn decrypt(
+                &self,
+                keys: &Self::Keys,
+                root: &$crate::MerkleRoot,
+            ) -> Result<Self::PlainText, $crate::CryptoError> {
+                use $crate::crypto::AttributeField;
+
+                Ok($ty {
+                    $($field: AttributeField::decrypt_field(&self.$field, &keys.$field, $attr, root)?),+
+                })
+            }
+        }
+    };
+}
+
+mod actor;
 mod aux;
 mod fireproof;
 mod key;
+mod revocation;
 
+pub use actor::*;
 pub use aux::*;
 pub use fireproof::*;
 pub use key::*;
+pub use revocation::*;
 
 /// A hack to get around Rust not having type functions
 ///
@@ -54,12 +97,24 @@ impl SymmetricKey {
     ///
     /// # Example
     /// ```
+    /// use pkd_core::action::SymmetricKey;
+    ///
     /// // SAFETY: Don't store secrets' plaintext in your executable like this!
     /// let secret = SymmetricKey::init(|v| v.extend_from_slice(b"foo"));
     /// ```
     pub fn init<F: FnOnce(&mut Vec<u8>)>(f: F) -> Self {
         Self(secrecy::SecretBox::init_with_mut(f))
     }
+
+    /// Generate a fresh 256-bit [`SymmetricKey`] using the OS's random number generator
+    pub fn generate() -> Result<Self, CryptoError> {
+        let mut ret = Ok(());
+        let key = Self::init(|v| {
+            v.resize(32, 0);
+            ret = getrandom::fill(v);
+        });
+        ret.map(|_| key).map_err(CryptoError::from)
+    }
 }
 
 // Constant time compare
@@ -88,9 +143,38 @@ impl Wrap for SymmetricKey {
     type Wrapper<T> = Self;
 }
 
+/// Encrypt every attribute of a [`PlainText`] message.
+///
+/// Each attribute is encrypted with its own freshly generated [`SymmetricKey`].
+pub trait Encrypt {
+    /// The [`CipherText`] version of this message
+    type CipherText;
+    /// The [`SymmetricKey`] version of this message
+    type Keys;
+
+    /// Encrypt this message, returning the ciphertext and the keys used to encrypt it.
+    ///
+    /// `root` is the `recent-merkle-root` of the protocol message this is going to be sent in.
+    fn encrypt(&self, root: &MerkleRoot) -> Result<(Self::CipherText, Self::Keys), CryptoError>;
+}
+
+/// Decrypt every attribute of a [`CipherText`] message.
+pub trait Decrypt {
+    /// The [`PlainText`] version of this message
+    type PlainText;
+    /// The [`SymmetricKey`] version of this message
+    type Keys;
+
+    /// Decrypt this message using `keys`.
+    ///
+    /// `root` is the `recent-merkle-root` of the protocol message this was sent in.
+    fn decrypt(&self, keys: &Self::Keys, root: &MerkleRoot)
+    -> Result<Self::PlainText, CryptoError>;
+}
+
 /// PKD protocol messages
 #[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
-#[serde(tag = "action")]
+#[serde(tag = "action", rename_all_fields = "kebab-case")]
 pub enum Action {
     /// The [`AddKey`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#addkey) PDK message
     AddKey(AddOrRevokeKey),
@@ -133,8 +217,92 @@ pub enum Action {
     },
 }
 
-/// A concrete id for a fediverse Actor
-pub type ActorId = String;
+impl Action {
+    /// The value of the `action` attribute for this message
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::AddKey(_) => "AddKey",
+            Self::RevokeKey(_) => "RevokeKey",
+            Self::RevokeKeyThirdParty { .. } => "RevokeKeyThirdParty",
+            Self::MoveIdentity { .. } => "MoveIdentity",
+            Self::BurnDown { .. } => "BurnDown",
+            Self::Fireproof(_) => "Fireproof",
+            Self::UndoFireproof(_) => "UndoFireproof",
+            Self::AddAuxData(_) => "AddAuxData",
+            Self::RevokeAuxData(_) => "RevokeAuxData",
+            Self::Checkpoint { .. } => "Checkpoint",
+        }
+    }
+
+    /// Decrypt the attributes of this message with its `symmetric-keys`.
+    ///
+    /// `root` is the `recent-merkle-root` of the protocol message this was sent in. It's unused for
+    /// [`Action::RevokeKeyThirdParty`] and [`Action::Checkpoint`], which have no encrypted attributes.
+    pub fn decrypt(&self, root: &MerkleRoot) -> Result<DecryptedAction, CryptoError> {
+        Ok(match self {
+            Self::AddKey(m) => {
+                DecryptedAction::AddKey(m.message.inner.decrypt(&m.symmetric_keys, root)?)
+            }
+            Self::RevokeKey(m) => {
+                DecryptedAction::RevokeKey(m.message.inner.decrypt(&m.symmetric_keys, root)?)
+            }
+            Self::RevokeKeyThirdParty { revocation_token } => {
+                DecryptedAction::RevokeKeyThirdParty(revocation_token.clone())
+            }
+            Self::MoveIdentity {
+                message,
+                symmetric_keys,
+            } => DecryptedAction::MoveIdentity(message.inner.decrypt(symmetric_keys, root)?),
+            Self::BurnDown {
+                message,
+                symmetric_keys,
+                ..
+            } => DecryptedAction::BurnDown(message.inner.decrypt(symmetric_keys, root)?),
+            Self::Fireproof(m) => {
+                DecryptedAction::Fireproof(m.message.inner.decrypt(&m.symmetric_keys, root)?)
+            }
+            Self::UndoFireproof(m) => {
+                DecryptedAction::UndoFireproof(m.message.inner.decrypt(&m.symmetric_keys, root)?)
+            }
+            Self::AddAuxData(m) => DecryptedAction::AddAuxData(AuxData {
+                aux_type: m.message.inner.aux_type.clone(),
+                aux_id: m.message.inner.aux_id.clone(),
+                inner: m.message.inner.inner.decrypt(&m.symmetric_keys, root)?,
+            }),
+            Self::RevokeAuxData(m) => DecryptedAction::RevokeAuxData(AuxData {
+                aux_type: m.message.inner.aux_type.clone(),
+                aux_id: m.message.inner.aux_id.clone(),
+                inner: m.message.inner.inner.decrypt(&m.symmetric_keys, root)?,
+            }),
+            Self::Checkpoint { message } => DecryptedAction::Checkpoint(message.inner.clone()),
+        })
+    }
+}
+
+/// The plaintext attributes of an [`Action`], see [`Action::decrypt`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecryptedAction {
+    /// A decrypted [`Action::AddKey`]
+    AddKey(AddOrRevokeKeyInner<PlainText>),
+    /// A decrypted [`Action::RevokeKey`]
+    RevokeKey(AddOrRevokeKeyInner<PlainText>),
+    /// An [`Action::RevokeKeyThirdParty`], which has nothing to decrypt
+    RevokeKeyThirdParty(RevocationToken),
+    /// A decrypted [`Action::MoveIdentity`]
+    MoveIdentity(MoveIdentity<PlainText>),
+    /// A decrypted [`Action::BurnDown`]
+    BurnDown(BurnDown<PlainText>),
+    /// A decrypted [`Action::Fireproof`]
+    Fireproof(FireproofInner<PlainText>),
+    /// A decrypted [`Action::UndoFireproof`]
+    UndoFireproof(FireproofInner<PlainText>),
+    /// A decrypted [`Action::AddAuxData`]
+    AddAuxData(AuxData<AddAuxDataInner<PlainText>>),
+    /// A decrypted [`Action::RevokeAuxData`]
+    RevokeAuxData(AuxData<RevokeAuxDataInner<PlainText>>),
+    /// An [`Action::Checkpoint`], which has nothing to decrypt
+    Checkpoint(Checkpoint),
+}
 
 /// [`AddKey`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#addkey) PKD protocol message
 #[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
@@ -146,6 +314,11 @@ pub struct MoveIdentity<M: Wrap> {
     pub new_actor: M::Wrapper<ActorId>,
 }
 
+impl_encrypt!(MoveIdentity {
+    old_actor => "old-actor",
+    new_actor => "new-actor",
+});
+
 /// [`AddKey`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#addkey) PKD protocol message
 #[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -156,8 +329,13 @@ pub struct BurnDown<M: Wrap> {
     pub operator: M::Wrapper<ActorId>,
 }
 
+impl_encrypt!(BurnDown {
+    actor => "actor",
+    operator => "operator",
+});
+
 /// [`AddKey`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#addkey) PKD protocol message
-#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Checkpoint {
     /// The public URL of the PKD sending this Message
@@ -172,7 +350,54 @@ pub struct Checkpoint {
     pub to_validated_root: MerkleRoot,
 }
 
-///  a compact token that a user can issue at any time to revoke an existing public key
-#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
-#[serde(transparent)]
-pub struct RevocationToken(String);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: MerkleRoot = MerkleRoot::new([0xab; 32]);
+
+    #[test]
+    fn encrypt_decrypt_roundtrip() {
+        let plain = AddOrRevokeKeyInner::<PlainText> {
+            actor: "https://example.com/users/alice".parse().unwrap(),
+            public_key: PublicKey::new([0x11; 32]),
+        };
+        let (cipher, keys) = plain.encrypt(&ROOT).unwrap();
+        assert_ne!(keys.actor, keys.public_key);
+        assert_eq!(cipher.decrypt(&keys, &ROOT).unwrap(), plain);
+
+        // Keys aren't interchangeable between attributes
+        let swapped = AddOrRevokeKeyInner::<SymmetricKey> {
+            actor: keys.public_key,
+            public_key: keys.actor,
+        };
+        assert!(cipher.decrypt(&swapped, &ROOT).is_err());
+    }
+
+    #[test]
+    fn encrypt_decrypt_optional() {
+        let plain = RevokeAuxDataInner::<PlainText> {
+            actor: "https://example.com/users/alice".parse().unwrap(),
+            aux_data: None,
+        };
+        let (cipher, keys) = plain.encrypt(&ROOT).unwrap();
+        assert!(cipher.aux_data.is_none() && keys.aux_data.is_none());
+        assert_eq!(cipher.decrypt(&keys, &ROOT).unwrap(), plain);
+
+        let plain = RevokeAuxDataInner::<PlainText> {
+            aux_data: Some(b"age1qyqszqgpqyqszqgpqyqszqgpqyqszqgp".to_vec()),
+            ..plain
+        };
+        let (cipher, keys) = plain.encrypt(&ROOT).unwrap();
+        assert_eq!(cipher.decrypt(&keys, &ROOT).unwrap(), plain);
+
+        let missing = RevokeAuxDataInner::<SymmetricKey> {
+            actor: keys.actor,
+            aux_data: None,
+        };
+        assert!(matches!(
+            cipher.decrypt(&missing, &ROOT),
+            Err(CryptoError::MissingKey(attr)) if attr == "aux-data"
+        ));
+    }
+}
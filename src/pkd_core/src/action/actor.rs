@@ -0,0 +1,389 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::crypto::AttributeValue;
+
+/// Errors from parsing an [`ActorId`] or a [`Handle`]
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ActorIdError {
+    /// The Actor ID isn't an `https://` URL
+    #[error("actor ID must be an https:// URL")]
+    Scheme,
+    /// The URL has no host
+    #[error("actor ID is missing a host")]
+    MissingHost,
+    /// The host isn't a valid DNS name, IP address or port
+    #[error("invalid host '{0}'")]
+    InvalidHost(String),
+    /// The URL has a `user:password@` component
+    #[error("actor ID must not contain credentials")]
+    Credentials,
+    /// The URL has a `#fragment`
+    #[error("actor ID must not contain a fragment")]
+    Fragment,
+    /// The URL contains whitespace or control characters
+    #[error("actor ID contains whitespace or control characters")]
+    InvalidCharacter,
+    /// The handle isn't in the form `@user@host`
+    #[error("expected a handle in the form '@user@host'")]
+    Handle,
+}
+
+/// A canonical [ActivityPub](https://www.w3.org/TR/activitypub/#obj-id) Actor ID, e.g. `https://example.com/users/alice`
+///
+/// Parsing normalizes the scheme and host to lowercase and drops the default `:443` port, so two spellings of the same
+/// Actor ID compare equal. Resolving a [`Handle`] to an [`ActorId`] is left to the client.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct ActorId(String);
+
+impl ActorId {
+    const SCHEME: &'static str = "https://";
+
+    /// Return the normalized Actor ID
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The `host[:port]` of the instance this actor lives on
+    pub fn authority(&self) -> &str {
+        let rest = &self.0[Self::SCHEME.len()..];
+        let end = rest.find(['/', '?']).unwrap_or(rest.len());
+        &rest[..end]
+    }
+
+    /// The host name of the instance this actor lives on, without a port
+    ///
+    /// # Example
+    /// ```
+    /// use pkd_core::action::ActorId;
+    ///
+    /// let actor: ActorId = "https://Example.COM:443/users/alice".parse().unwrap();
+    /// assert_eq!(actor.as_str(), "https://example.com/users/alice");
+    /// assert_eq!(actor.host(), "example.com");
+    /// ```
+    pub fn host(&self) -> &str {
+        split_port(self.authority()).0
+    }
+
+    /// Whether `self` and `other` live on the same instance
+    pub fn same_instance(&self, other: &ActorId) -> bool {
+        self.authority() == other.authority()
+    }
+
+    /// Percent-encode this Actor ID as a single path segment, e.g. for `GET api/actor/:actor_id`
+    ///
+    /// # Example
+    /// ```
+    /// use pkd_core::action::ActorId;
+    ///
+    /// let actor: ActorId = "https://example.com/users/alice".parse().unwrap();
+    /// assert_eq!(actor.to_path_segment(), "https%3A%2F%2Fexample.com%2Fusers%2Falice");
+    /// ```
+    pub fn to_path_segment(&self) -> String {
+        crate::api::path_segment(&self.0)
+    }
+}
+
+/// Split `authority` into its host and optional port
+fn split_port(authority: &str) -> (&str, Option<&str>) {
+    // IPv6 literals contain colons, so only look for a port after the closing bracket
+    let search_from = authority.rfind(']').unwrap_or(0);
+    match authority[search_from..].rfind(':') {
+        Some(i) => (
+            &authority[..search_from + i],
+            Some(&authority[search_from + i + 1..]),
+        ),
+        None => (authority, None),
+    }
+}
+
+/// Validate and lowercase `authority`, dropping the default port
+fn normalize_authority(authority: &str) -> Result<String, ActorIdError> {
+    if authority.contains('@') {
+        return Err(ActorIdError::Credentials);
+    }
+    let (host, port) = split_port(authority);
+    if host.is_empty() {
+        return Err(ActorIdError::MissingHost);
+    }
+    let host = host.to_ascii_lowercase();
+    let valid_host = match host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        Some(ipv6) => ipv6.parse::<std::net::Ipv6Addr>().is_ok(),
+        // Internationalized domain names must already be punycoded
+        None => host.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        }),
+    };
+    let port = match port {
+        Some(p) if p.bytes().all(|b| b.is_ascii_digit()) => p.parse::<u16>().ok(),
+        Some(_) => None,
+        None => Some(443),
+    };
+    match port {
+        Some(443) if valid_host => Ok(host),
+        Some(port) if valid_host && port != 0 => Ok(format!("{host}:{port}")),
+        _ => Err(ActorIdError::InvalidHost(authority.to_string())),
+    }
+}
+
+impl FromStr for ActorId {
+    type Err = ActorIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(ActorIdError::InvalidCharacter);
+        }
+        let rest = s
+            .get(..Self::SCHEME.len())
+            .filter(|scheme| scheme.eq_ignore_ascii_case(Self::SCHEME))
+            .map(|_| &s[Self::SCHEME.len()..])
+            .ok_or(ActorIdError::Scheme)?;
+        if rest.contains('#') {
+            return Err(ActorIdError::Fragment);
+        }
+        let end = rest.find(['/', '?']).unwrap_or(rest.len());
+        let (authority, path) = rest.split_at(end);
+        let authority = normalize_authority(authority)?;
+        Ok(Self(format!("{}{authority}{path}", Self::SCHEME)))
+    }
+}
+
+impl Display for ActorId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<ActorId> for String {
+    fn from(actor: ActorId) -> Self {
+        actor.0
+    }
+}
+
+impl AttributeValue for ActorId {
+    fn to_attribute_bytes(&self) -> Vec<u8> {
+        self.0.as_bytes().to_vec()
+    }
+
+    fn from_attribute_bytes(bytes: Vec<u8>) -> Option<Self> {
+        let actor = String::from_utf8(bytes).ok()?;
+        // The commitment covers these exact bytes, so reject rather than normalize a non-canonical Actor ID
+        let canonical: Self = actor.parse().ok()?;
+        (canonical.0 == actor).then_some(Self(actor))
+    }
+}
+
+impl serde::Serialize for ActorId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ActorId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ActorIdVisitor;
+
+        impl serde::de::Visitor<'_> for ActorIdVisitor {
+            type Value = ActorId;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("an https:// Actor ID")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(ActorIdVisitor)
+    }
+}
+
+/// A [WebFinger](https://datatracker.ietf.org/doc/html/rfc7033)-style handle, e.g. `@alice@example.com`
+///
+/// Handles are aliases, not Actor IDs. Clients must resolve them to an [`ActorId`] before using them in messages.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct Handle {
+    user: String,
+    host: String,
+}
+
+impl Handle {
+    /// The user part of the handle
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    /// The `host[:port]` of the instance the handle lives on
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// The `acct:` URI to query WebFinger with
+    ///
+    /// # Example
+    /// ```
+    /// use pkd_core::action::Handle;
+    ///
+    /// let handle: Handle = "@alice@Example.com".parse().unwrap();
+    /// assert_eq!(handle.to_string(), "@alice@example.com");
+    /// assert_eq!(handle.webfinger_resource(), "acct:alice@example.com");
+    /// ```
+    pub fn webfinger_resource(&self) -> String {
+        format!("acct:{}@{}", self.user, self.host)
+    }
+}
+
+impl FromStr for Handle {
+    type Err = ActorIdError;
+
+    /// Parse `@user@host`, `user@host` or `acct:user@host`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s
+            .strip_prefix("acct:")
+            .or_else(|| s.strip_prefix('@'))
+            .unwrap_or(s);
+        let (user, host) = s.split_once('@').ok_or(ActorIdError::Handle)?;
+        let valid_user = !user.is_empty()
+            && user
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"_-.".contains(&b));
+        if !valid_user {
+            return Err(ActorIdError::Handle);
+        }
+        Ok(Self {
+            user: user.to_string(),
+            host: normalize_authority(host)?,
+        })
+    }
+}
+
+impl Display for Handle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "@{}@{}", self.user, self.host)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ActorId, ActorIdError, Handle};
+    use crate::crypto::AttributeValue;
+
+    #[test]
+    fn parse_actor_id() {
+        let actor: ActorId = " HTTPS://Example.com:443/users/Alice?x=1 ".parse().unwrap();
+        assert_eq!(actor.as_str(), "https://example.com/users/Alice?x=1");
+        assert_eq!(actor.host(), "example.com");
+
+        let actor: ActorId = "https://example.com:8443".parse().unwrap();
+        assert_eq!(actor.authority(), "example.com:8443");
+        assert_eq!(actor.host(), "example.com");
+
+        let actor: ActorId = "https://[::1]:8443/alice".parse().unwrap();
+        assert_eq!(actor.host(), "[::1]");
+
+        let alice: ActorId = "https://example.com/alice".parse().unwrap();
+        let bob: ActorId = "https://EXAMPLE.com/bob".parse().unwrap();
+        assert!(alice.same_instance(&bob));
+        assert!(!alice.same_instance(&actor));
+    }
+
+    #[test]
+    fn reject_actor_id() {
+        let cases = [
+            ("", ActorIdError::Scheme),
+            ("http://example.com/alice", ActorIdError::Scheme),
+            ("acct:alice@example.com", ActorIdError::Scheme),
+            ("@alice@example.com", ActorIdError::Scheme),
+            ("https:///alice", ActorIdError::MissingHost),
+            (
+                "https://user:pw@example.com/alice",
+                ActorIdError::Credentials,
+            ),
+            ("https://example.com/alice#main-key", ActorIdError::Fragment),
+            ("https://example.com/al ice", ActorIdError::InvalidCharacter),
+            (
+                "https://example.com/\nalice",
+                ActorIdError::InvalidCharacter,
+            ),
+        ];
+        for (input, err) in cases {
+            assert_eq!(input.parse::<ActorId>(), Err(err), "{input:?}");
+        }
+        for input in [
+            "https://exa_mple.com",
+            "https://example..com",
+            "https://-example.com",
+            "https://example.com:0",
+            "https://example.com:99999",
+            "https://[not-ipv6]",
+        ] {
+            assert!(
+                matches!(input.parse::<ActorId>(), Err(ActorIdError::InvalidHost(_))),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn actor_id_serde() {
+        let actor: ActorId = serde_json::from_str("\"https://Example.com/alice\"").unwrap();
+        assert_eq!(
+            serde_json::to_string(&actor).unwrap(),
+            "\"https://example.com/alice\""
+        );
+        assert!(serde_json::from_str::<ActorId>("\"example.com/alice\"").is_err());
+    }
+
+    #[test]
+    fn actor_id_attribute() {
+        let actor: ActorId = "https://example.com/alice".parse().unwrap();
+        assert_eq!(
+            ActorId::from_attribute_bytes(actor.to_attribute_bytes()),
+            Some(actor)
+        );
+        // Decrypted plaintext isn't normalized, it must already be canonical
+        for input in [
+            "https://Example.com/alice",
+            "https://example.com:443/alice",
+            " https://example.com/alice",
+            "example.com/alice",
+        ] {
+            assert_eq!(
+                ActorId::from_attribute_bytes(input.as_bytes().to_vec()),
+                None,
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_handle() {
+        for input in [
+            "@alice@example.com",
+            "alice@Example.com",
+            "acct:alice@example.com",
+        ] {
+            let handle: Handle = input.parse().unwrap();
+            assert_eq!(handle.user(), "alice");
+            assert_eq!(handle.host(), "example.com");
+        }
+        for input in ["alice", "@alice", "@@example.com", "@al/ice@example.com"] {
+            assert_eq!(
+                input.parse::<Handle>(),
+                Err(ActorIdError::Handle),
+                "{input:?}"
+            );
+        }
+        assert_eq!(
+            "@alice@user@example.com".parse::<Handle>(),
+            Err(ActorIdError::Credentials)
+        );
+    }
+}
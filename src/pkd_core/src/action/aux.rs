@@ -1,8 +1,27 @@
+use base64ct::{Base64UrlUnpadded, Encoding};
+use hmac::{Hmac, Mac};
+
 use crate::{
     action::{ActorId, CipherText, SymmetricKey, Wrap},
+    crypto::pae,
     utils::Timestamped,
 };
 
+//= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#auxiliary-data-identifiers
+//# b'FediPKD1-Auxiliary-Data-IDKeyGen', # this key is a constant for v1 of this protocol specification
+const AUX_ID_KEY: &[u8] = b"FediPKD1-Auxiliary-Data-IDKeyGen";
+
+/// Compute the [Auxiliary Data Identifier](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#auxiliary-data-identifiers)
+/// for `data` of the extension `aux_type`, encoded in unpadded base64url.
+pub fn aux_data_id(aux_type: &str, data: &[u8]) -> String {
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#auxiliary-data-identifiers
+    //# Every Auxiliary Data will have a deterministic unique identifier based on the extension and the contents of the data.
+    let mut mac =
+        Hmac::<sha2::Sha256>::new_from_slice(AUX_ID_KEY).expect("HMAC to accept keys of any size");
+    mac.update(&pae(&[b"aux_type", aux_type.as_bytes(), b"data", data]));
+    Base64UrlUnpadded::encode_string(&mac.finalize().into_bytes())
+}
+
 /// The [`AddAuxData`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#addauxdata) PDK message
 #[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -38,6 +57,25 @@ pub struct AuxData<T> {
     pub inner: T,
 }
 
+impl<T> AuxData<T> {
+    /// Create [`AuxData`] with its `aux-id` computed from the plaintext `data`.
+    pub fn new(aux_type: String, data: &[u8], inner: T) -> Self {
+        let aux_id = Some(aux_data_id(&aux_type, data));
+        Self {
+            aux_type,
+            aux_id,
+            inner,
+        }
+    }
+
+    /// Check whether `aux-id`, if present, is valid for the plaintext `data`.
+    pub fn is_valid_id(&self, data: &[u8]) -> bool {
+        self.aux_id
+            .as_ref()
+            .is_none_or(|id| *id == aux_data_id(&self.aux_type, data))
+    }
+}
+
 /// [`AddAuxData`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#addauxdata) PKD protocol message attributes
 #[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -52,6 +90,11 @@ pub struct AddAuxDataInner<M: Wrap> {
     pub aux_data: M::Wrapper<Vec<u8>>,
 }
 
+impl_encrypt!(AddAuxDataInner {
+    actor => "actor",
+    aux_data => "aux-data",
+});
+
 /// [`RevokeAuxData`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#revokeauxdata) PKD protocol message attributes
 #[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -65,3 +108,47 @@ pub struct RevokeAuxDataInner<M: Wrap> {
     /// The auxiliary data.
     pub aux_data: Option<M::Wrapper<Vec<u8>>>,
 }
+
+impl_encrypt!(RevokeAuxDataInner {
+    actor => "actor",
+    aux_data => "aux-data",
+});
+
+#[cfg(test)]
+mod tests {
+    use super::{AuxData, aux_data_id};
+
+    #[test]
+    fn aux_id() {
+        let id = aux_data_id("age-v1", b"age1examplekey");
+        assert_eq!(id.len(), 43);
+        assert_eq!(id, aux_data_id("age-v1", b"age1examplekey"));
+        assert_ne!(id, aux_data_id("age-v2", b"age1examplekey"));
+        assert_ne!(id, aux_data_id("age-v1", b"age1otherkey"));
+        // PAE prevents shifting bytes between the type and data
+        assert_ne!(aux_data_id("ab", b"c"), aux_data_id("a", b"bc"));
+    }
+
+    /// Computed independently with Python's `hmac` over the spec's PAE framing
+    #[test]
+    fn aux_id_known_answer() {
+        assert_eq!(
+            aux_data_id("age-v1", b"age1examplekey"),
+            "lw39EEZkYQFnTWLxXEYfXr_-bGDaTIhK72hHsyU6lx0"
+        );
+    }
+
+    #[test]
+    fn new_fills_id() {
+        let aux = AuxData::new("age-v1".to_string(), b"age1examplekey", ());
+        assert_eq!(aux.aux_id, Some(aux_data_id("age-v1", b"age1examplekey")));
+        assert!(aux.is_valid_id(b"age1examplekey"));
+        assert!(!aux.is_valid_id(b"age1otherkey"));
+
+        let aux = AuxData {
+            aux_id: None,
+            ..aux
+        };
+        assert!(aux.is_valid_id(b"anything"));
+    }
+}
@@ -20,3 +20,5 @@ pub struct FireproofInner<M: Wrap> {
     /// The canonical Actor ID for a given ActivityPub user.
     pub actor: M::Wrapper<ActorId>,
 }
+
+impl_encrypt!(FireproofInner { actor => "actor" });
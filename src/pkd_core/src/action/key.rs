@@ -23,3 +23,8 @@ pub struct AddOrRevokeKeyInner<M: Wrap> {
     /// The public key to add or revoke.
     pub public_key: M::Wrapper<PublicKey>,
 }
+
+impl_encrypt!(AddOrRevokeKeyInner {
+    actor => "actor",
+    public_key => "public-key",
+});
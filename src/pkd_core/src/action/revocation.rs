@@ -0,0 +1,169 @@
+use base64ct::{Base64UrlUnpadded, Encoding};
+
+use crate::{PublicKey, Signature, SignatureError, SigningKey};
+
+//= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revocation-tokens
+//# * `version` is the version of the protocol being used (currently, `FediPKD1`).
+const VERSION: &[u8] = b"FediPKD1";
+//= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revocation-tokens
+//# Its current value is
+//# the `0xFE` byte repeated 32 times followed by `revoke-public-key`.
+const REVOCATION_CONSTANT: &[u8] = b"\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xfe\xferevoke-public-key";
+/// Length of `version || REVOCATION_CONSTANT || public_key`
+const SIGNED_LEN: usize = VERSION.len() + REVOCATION_CONSTANT.len() + 32;
+/// Length of a decoded revocation token
+const TOKEN_LEN: usize = SIGNED_LEN + 64;
+
+/// Errors from verifying a [`RevocationToken`]
+#[derive(Debug, thiserror::Error)]
+pub enum RevocationError {
+    /// The token isn't valid base64url
+    #[error("failed to decode base64url")]
+    Base64,
+    /// The decoded token has the wrong length
+    #[error("invalid token length, expected {TOKEN_LEN} found {0}")]
+    Length(usize),
+    /// The token doesn't start with a supported `version`
+    #[error("unsupported revocation token version")]
+    Version,
+    /// The token doesn't contain the `REVOCATION_CONSTANT`
+    #[error("invalid revocation constant")]
+    Constant,
+    /// The token's signature is invalid
+    #[error(transparent)]
+    Signature(#[from] SignatureError),
+}
+
+///  a compact token that a user can issue at any time to revoke an existing public key
+///
+/// See [Revocation Tokens](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#revocation-tokens)
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct RevocationToken(String);
+
+impl RevocationToken {
+    /// Issue a token that revokes the public key of `key`.
+    ///
+    /// There is no temporal or random component, so the token can be stored and used at any time.
+    pub fn issue(key: &SigningKey) -> Self {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revocation-tokens
+        //# tmp := version || REVOCATION_CONSTANT || public_key
+        //# revocation_token := base64url_encode(tmp || Sign(secret_key, tmp))
+        let mut token = Vec::with_capacity(TOKEN_LEN);
+        token.extend_from_slice(VERSION);
+        token.extend_from_slice(REVOCATION_CONSTANT);
+        token.extend_from_slice(&key.public_key().0);
+        let signature = key.sign(&token);
+        token.extend_from_slice(&signature.0);
+        Self(Base64UrlUnpadded::encode_string(&token))
+    }
+
+    /// Verify this token, returning the [`PublicKey`] it revokes.
+    pub fn verify(&self) -> Result<PublicKey, RevocationError> {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekeythirdparty-validation-steps
+        //# Decode the revocation token from base64url.
+        let token = Base64UrlUnpadded::decode_vec(&self.0).map_err(|_| RevocationError::Base64)?;
+        if token.len() != TOKEN_LEN {
+            return Err(RevocationError::Length(token.len()));
+        }
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekeythirdparty-validation-steps
+        //# Split the revocation token into `version`, `REVOCATION_CONSTANT`, `public_key`, and `signature`
+        let (signed, signature) = token.split_at(SIGNED_LEN);
+        let (version, rest) = signed.split_at(VERSION.len());
+        let (constant, public_key) = rest.split_at(REVOCATION_CONSTANT.len());
+        if version != VERSION {
+            return Err(RevocationError::Version);
+        }
+        if constant != REVOCATION_CONSTANT {
+            return Err(RevocationError::Constant);
+        }
+        let public_key = PublicKey::new(public_key.try_into().expect("public key to be 32 bytes"));
+        let signature = Signature(signature.try_into().expect("signature to be 64 bytes"));
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekeythirdparty-validation-steps
+        //# Validate signature for  `version || REVOCATION_CONSTANT || public_key`, using `public_key`.
+        public_key.verify(signed, &signature)?;
+        Ok(public_key)
+    }
+
+    /// Return the encoded token
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for RevocationToken {
+    fn from(token: String) -> Self {
+        Self(token)
+    }
+}
+
+impl std::fmt::Display for RevocationToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64ct::{Base64UrlUnpadded, Encoding};
+
+    use super::{RevocationError, RevocationToken};
+    use crate::{SignatureError, SigningKey};
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekeythirdparty-validation-steps
+    //= type=test
+    //# Validate signature for  `version || REVOCATION_CONSTANT || public_key`, using `public_key`.
+    #[test]
+    fn issue_verify() {
+        let sk = SigningKey::from_bytes(&[3; 32]);
+        let token = RevocationToken::issue(&sk);
+        assert_eq!(token, RevocationToken::issue(&sk));
+        assert_eq!(token.verify().unwrap(), sk.public_key());
+
+        let decoded = Base64UrlUnpadded::decode_vec(token.as_str()).unwrap();
+        assert_eq!(&decoded[..8], b"FediPKD1");
+        assert_eq!(&decoded[8..40], &[0xfe; 32]);
+        assert_eq!(&decoded[40..57], b"revoke-public-key");
+        assert_eq!(&decoded[57..89], &sk.public_key().0);
+    }
+
+    #[test]
+    fn verify_rejects() {
+        let token = RevocationToken::issue(&SigningKey::from_bytes(&[3; 32]));
+        let decoded = Base64UrlUnpadded::decode_vec(token.as_str()).unwrap();
+        let encode = |bytes: &[u8]| RevocationToken::from(Base64UrlUnpadded::encode_string(bytes));
+
+        assert!(matches!(
+            RevocationToken::from("not base64!".to_string()).verify(),
+            Err(RevocationError::Base64)
+        ));
+        assert!(matches!(
+            encode(&decoded[1..]).verify(),
+            Err(RevocationError::Length(152))
+        ));
+
+        let mut bad = decoded.clone();
+        bad[7] = b'2';
+        assert!(matches!(
+            encode(&bad).verify(),
+            Err(RevocationError::Version)
+        ));
+
+        let mut bad = decoded.clone();
+        bad[8] = 0;
+        assert!(matches!(
+            encode(&bad).verify(),
+            Err(RevocationError::Constant)
+        ));
+
+        // Swap in another public key
+        let mut bad = decoded.clone();
+        bad[57..89].copy_from_slice(&SigningKey::from_bytes(&[4; 32]).public_key().0);
+        assert!(matches!(
+            encode(&bad).verify(),
+            Err(RevocationError::Signature(SignatureError::Invalid))
+        ));
+    }
+}
@@ -0,0 +1,611 @@
+//! Request and response bodies of the [JSON REST API](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#json-rest-api)
+//!
+//! Every body carries its own `!pkd-context`, so deserializing a response from the wrong endpoint fails.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    Clock, HpkePublicKey, KeyId, MerkleHash, MerkleRoot, PublicKey, Timestamp,
+    action::{ActorId, RevocationToken},
+    utils::{Context, ContextValue},
+};
+
+/// Defines a [`ContextValue`] tag for the `!pkd-context` of each endpoint
+macro_rules! api_context {
+    ($($tag:ident => $context:literal,)+) => {
+        $(
+            #[doc = concat!("A [`Context`] tag for `", $context, "` bodies")]
+            #[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+            pub struct $tag;
+
+            impl ContextValue for $tag {
+                const CONTEXT: &'static str = $context;
+            }
+        )+
+    };
+}
+
+api_context! {
+    ActorInfoContext => "fedi-e2ee:v1/api/actor/info",
+    ActorKeysContext => "fedi-e2ee:v1/api/actor/get-keys",
+    KeyInfoContext => "fedi-e2ee:v1/api/actor/key-info",
+    ActorAuxContext => "fedi-e2ee:v1/api/actor/aux-info",
+    AuxInfoContext => "fedi-e2ee:v1/api/actor/get-aux",
+    HistoryContext => "fedi-e2ee:v1/api/history",
+    HistorySinceContext => "fedi-e2ee:v1/api/history/since",
+    HistoryViewContext => "fedi-e2ee:v1/api/history/view",
+    ExtensionsContext => "fedi-e2ee:v1/api/extensions",
+    ReplicasContext => "fedi-e2ee:v1/api/replicas",
+    ServerPublicKeyContext => "fedi-e2ee:v1/api/server-public-key",
+    RevokeContext => "fedi-e2ee:v1/api/revoke",
+}
+
+/// Percent-encode `value` as a single path segment of an API URL, escaping everything but RFC 3986 unreserved
+/// characters
+///
+/// # Example
+/// ```
+/// assert_eq!(pkd_core::api::path_segment("a/b c~"), "a%2Fb%20c~");
+/// ```
+pub fn path_segment(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() * 3);
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+/// Symmetric keys re-wrapped for each Trusted Replica, by the replica's domain name and then by attribute
+pub type RewrappedKeys = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Response of [`GET api/actor/:actor_id`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apiactoractor_id)
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ActorInfo {
+    /// Domain separation
+    #[serde(rename = "!pkd-context")]
+    pub context: Context<ActorInfoContext>,
+    /// The requested actor
+    pub actor_id: ActorId,
+    /// The number of auxiliary data records for this actor
+    pub count_aux: u64,
+    /// The number of active public keys for this actor
+    pub count_keys: u64,
+}
+
+/// Response of [`GET api/actor/:actor_id/keys`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apiactoractor_idkeys)
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ActorKeys {
+    /// Domain separation
+    #[serde(rename = "!pkd-context")]
+    pub context: Context<ActorKeysContext>,
+    /// The requested actor
+    pub actor_id: ActorId,
+    /// The actor's non-revoked public keys
+    pub public_keys: Vec<KeyRecord>,
+}
+
+/// A public key in [`ActorKeys`]
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct KeyRecord {
+    /// When the key was added
+    pub created: Timestamp,
+    /// The key's `key-id`
+    pub key_id: KeyId,
+    /// The audit path of the `AddKey` message
+    pub inclusion_proof: Vec<MerkleHash>,
+    /// The Merkle root after the `AddKey` message
+    pub merkle_root: MerkleRoot,
+    /// The public key
+    pub public_key: PublicKey,
+}
+
+/// Response of [`GET api/actor/:actor_id/key/:key_id`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apiactoractor_idkeykey_id)
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct KeyInfo {
+    /// Domain separation
+    #[serde(rename = "!pkd-context")]
+    pub context: Context<KeyInfoContext>,
+    /// The requested actor
+    pub actor_id: ActorId,
+    /// When the key was added
+    pub created: Timestamp,
+    /// The audit path of the `AddKey` message
+    pub inclusion_proof: Vec<MerkleHash>,
+    /// The key's `key-id`
+    pub key_id: KeyId,
+    /// The Merkle root after the `AddKey` message
+    pub merkle_root: MerkleRoot,
+    /// The public key
+    pub public_key: PublicKey,
+    /// When the key was revoked, if it was
+    pub revoked: Option<Timestamp>,
+    /// The Merkle root after the `RevokeKey` message, if the key was revoked
+    pub revoke_root: Option<MerkleRoot>,
+}
+
+/// Response of [`GET api/actor/:actor_id/auxiliary`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apiactoractor_idauxiliary)
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ActorAuxiliary {
+    /// Domain separation
+    #[serde(rename = "!pkd-context")]
+    pub context: Context<ActorAuxContext>,
+    /// The requested actor
+    pub actor_id: ActorId,
+    /// The actor's non-revoked auxiliary data
+    pub auxiliary: Vec<AuxSummary>,
+}
+
+/// An auxiliary data record in [`ActorAuxiliary`]
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AuxSummary {
+    /// The [Auxiliary Data Identifier](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#auxiliary-data-identifiers)
+    pub aux_id: String,
+    /// The identifier of the Auxiliary Data extension
+    pub aux_type: String,
+    /// When the auxiliary data was added
+    pub created: Timestamp,
+}
+
+/// Response of [`GET api/actor/:actor_id/auxiliary/:aux_data_id`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apiactoractor_idauxiliaryaux_data_id)
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AuxInfo {
+    /// Domain separation
+    #[serde(rename = "!pkd-context")]
+    pub context: Context<AuxInfoContext>,
+    /// The requested actor
+    pub actor_id: ActorId,
+    /// The auxiliary data
+    pub aux_data: String,
+    /// The [Auxiliary Data Identifier](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#auxiliary-data-identifiers)
+    pub aux_id: String,
+    /// The identifier of the Auxiliary Data extension
+    pub aux_type: String,
+    /// When the auxiliary data was added
+    pub created: Timestamp,
+    /// The audit path of the `AddAuxData` message
+    pub inclusion_proof: Vec<MerkleHash>,
+    /// The Merkle root after the `AddAuxData` message
+    pub merkle_root: MerkleRoot,
+    /// When the auxiliary data was revoked, if it was
+    pub revoked: Option<Timestamp>,
+    /// The Merkle root after the `RevokeAuxData` message, if the auxiliary data was revoked
+    pub revoke_root: Option<MerkleRoot>,
+}
+
+/// Response of [`GET api/history`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apihistory)
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct History {
+    /// Domain separation
+    #[serde(rename = "!pkd-context")]
+    pub context: Context<HistoryContext>,
+    /// The directory's current time
+    pub current_time: Timestamp,
+    /// When the latest record was created
+    pub created: Timestamp,
+    /// The Merkle root after the latest record
+    pub merkle_root: MerkleRoot,
+}
+
+/// Response of [`GET api/history/since/:last_hash`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apihistorysincelast_hash)
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HistorySince {
+    /// Domain separation
+    #[serde(rename = "!pkd-context")]
+    pub context: Context<HistorySinceContext>,
+    /// The directory's current time
+    pub current_time: Timestamp,
+    /// The records after the requested hash, in order
+    pub records: Vec<HistoryRecord>,
+}
+
+/// A record in [`HistorySince`]
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HistoryRecord {
+    /// When the record was created
+    pub created: Timestamp,
+    /// The protocol message with encrypted attributes as JSON, which is committed to the Merkle tree
+    pub encrypted_message: String,
+    /// The decrypted protocol message, unless its keys were shredded
+    pub message: Option<serde_json::Map<String, serde_json::Value>>,
+    /// The Merkle root after this record
+    pub merkle_root: MerkleRoot,
+    /// Symmetric keys re-wrapped for Trusted Replicas
+    #[serde(default)]
+    pub rewrapped_keys: Option<RewrappedKeys>,
+}
+
+/// Response of [`GET api/history/view/:hash`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apihistoryviewhash)
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HistoryView {
+    /// Domain separation
+    #[serde(rename = "!pkd-context")]
+    pub context: Context<HistoryViewContext>,
+    /// When the record was created
+    pub created: Timestamp,
+    /// The protocol message with encrypted attributes as JSON, which is committed to the Merkle tree
+    pub encrypted_message: String,
+    /// The audit path of the record
+    pub inclusion_proof: Vec<MerkleHash>,
+    /// The decrypted protocol message, unless its keys were shredded
+    pub message: Option<serde_json::Map<String, serde_json::Value>>,
+    /// The Merkle root after the latest record
+    pub merkle_root: MerkleRoot,
+    /// Symmetric keys re-wrapped for Trusted Replicas
+    #[serde(default)]
+    pub rewrapped_keys: Option<RewrappedKeys>,
+}
+
+/// Response of [`GET api/extensions`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apiextensions)
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Extensions {
+    /// Domain separation
+    #[serde(rename = "!pkd-context")]
+    pub context: Context<ExtensionsContext>,
+    /// The directory's current time
+    #[serde(alias = "time")]
+    pub current_time: Timestamp,
+    /// The supported Auxiliary Data extensions
+    pub extensions: Vec<Extension>,
+}
+
+/// An Auxiliary Data extension in [`Extensions`]
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Extension {
+    /// The extension's unique `aux-type`
+    pub id: String,
+    /// The extension-specific version
+    pub version: String,
+    /// URL of the extension's specification
+    #[serde(rename = "ref")]
+    pub reference: String,
+    /// Any extension-specific fields
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+/// Response of [`GET api/replicas`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apireplicas)
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Replicas {
+    /// Domain separation
+    #[serde(rename = "!pkd-context")]
+    pub context: Context<ReplicasContext>,
+    /// The directory's current time
+    #[serde(alias = "time")]
+    pub current_time: Timestamp,
+    /// The directories replicated onto this one
+    #[serde(alias = "extensions")]
+    pub replicas: Vec<Replica>,
+}
+
+/// A replicated directory in [`Replicas`]
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Replica {
+    /// The replica's unique id, used in `api/replica/:replica_id/...`
+    pub id: String,
+    /// Canonical URL of the replicated directory
+    #[serde(rename = "ref")]
+    pub reference: String,
+    /// Any additional fields
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+/// Response of [`GET api/server-public-key`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#get-apiserver-public-key)
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ServerPublicKey {
+    /// Domain separation
+    #[serde(rename = "!pkd-context")]
+    pub context: Context<ServerPublicKeyContext>,
+    /// The directory's current time
+    pub current_time: Timestamp,
+    /// The [HPKE cipher suite](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#hpke-cipher-suites)
+    /// for `hpke-public-key`, e.g. `Curve25519_SHA256_ChachaPoly`
+    pub hpke_ciphersuite: String,
+    /// The directory's HPKE public key
+    pub hpke_public_key: HpkePublicKey,
+}
+
+/// Request body of [`POST api/revoke`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#post-apirevoke)
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RevokeRequest {
+    /// Domain separation
+    #[serde(rename = "!pkd-context")]
+    pub context: Context<RevokeContext>,
+    /// The client's current time
+    pub current_time: Timestamp,
+    /// The token revoking a public key
+    pub revocation_token: RevocationToken,
+}
+
+impl RevokeRequest {
+    /// A request to revoke with `revocation_token` at the current time of `clock`
+    pub fn new(revocation_token: RevocationToken, clock: &impl Clock) -> Self {
+        Self {
+            context: Context::new(),
+            current_time: clock.now(),
+            revocation_token,
+        }
+    }
+}
+
+/// Response of [`POST api/revoke`](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#post-apirevoke)
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Revoked {
+    /// Domain separation
+    #[serde(rename = "!pkd-context")]
+    pub context: Context<RevokeContext>,
+    /// The directory's current time
+    #[serde(alias = "time")]
+    pub current_time: Timestamp,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+
+    /// Parse `body`, and check that it fails to parse with any other context
+    fn parse<T: serde::de::DeserializeOwned + serde::Serialize>(body: &str) -> T {
+        let parsed: T = json::from_str(body).unwrap();
+        let reparsed: T = json::from_str(&serde_json::to_string(&parsed).unwrap()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&parsed).unwrap(),
+            serde_json::to_value(&reparsed).unwrap()
+        );
+
+        let mut value: serde_json::Value = serde_json::from_str(body).unwrap();
+        let context = value["!pkd-context"].as_str().unwrap().to_string();
+        for other in [
+            ActorInfoContext::CONTEXT,
+            ActorKeysContext::CONTEXT,
+            KeyInfoContext::CONTEXT,
+            ActorAuxContext::CONTEXT,
+            AuxInfoContext::CONTEXT,
+            HistoryContext::CONTEXT,
+            HistorySinceContext::CONTEXT,
+            HistoryViewContext::CONTEXT,
+            ExtensionsContext::CONTEXT,
+            ReplicasContext::CONTEXT,
+            ServerPublicKeyContext::CONTEXT,
+            RevokeContext::CONTEXT,
+            crate::ProtocolContextTag::CONTEXT,
+        ] {
+            if other != context {
+                value["!pkd-context"] = other.into();
+                assert!(
+                    serde_json::from_value::<T>(value.clone()).is_err(),
+                    "{context} accepted {other}"
+                );
+            }
+        }
+        value.as_object_mut().unwrap().remove("!pkd-context");
+        assert!(serde_json::from_value::<T>(value).is_err());
+        parsed
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#get-apiactoractor_id
+    //= type=test
+    //# The `!pkd-context` field will be set to the ASCII string `fedi-e2ee:v1/api/actor/info`.
+    #[test]
+    fn actor_info() {
+        let info: ActorInfo = parse(
+            r#"{"!pkd-context":"fedi-e2ee:v1/api/actor/info","actor-id":"https://example.com/alice","count-aux":5,"count-keys":3}"#,
+        );
+        assert_eq!(info.actor_id.as_str(), "https://example.com/alice");
+        assert_eq!((info.count_aux, info.count_keys), (5, 3));
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#get-apiactoractor_idkeys
+    //= type=test
+    //# The `!pkd-context` field will be set to the ASCII string `fedi-e2ee:v1/api/actor/get-keys`.
+    #[test]
+    fn actor_keys() {
+        let keys: ActorKeys = parse(
+            r#"{"!pkd-context":"fedi-e2ee:v1/api/actor/get-keys","actor-id":"https://example.com/alice","public-keys":[{"created":"1722176511","key-id":"foo","inclusion-proof":["yWF2i5NqdyB9s0nEGnNQpoxSOcIwySMCch5xTOJurwE","b67xzsl8mtyMGphDa-DNJEyrM0lWIgp94W36svRxjW4"],"merkle-root":"pkd-mr-v1:rZgQvJn16wkOuNq3ejHqC0zDkuQ-3GBpCR0YP6Xy5yQ","public-key":"ed25519:Tm2XBvb0mAb4ldVubCzvz0HMTczR8VGF44sv478VFLM"}]}"#,
+        );
+        let [key] = keys.public_keys.as_slice() else {
+            panic!("expected one key")
+        };
+        assert_eq!(key.created, Timestamp::from_secs(1722176511));
+        assert_eq!(key.key_id.to_string(), "foo");
+        assert_eq!(key.inclusion_proof.len(), 2);
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#get-apiactoractor_idkeykey_id
+    //= type=test
+    //# The `!pkd-context` field will be set to the ASCII string `fedi-e2ee:v1/api/actor/key-info`.
+    #[test]
+    fn key_info() {
+        let info: KeyInfo = parse(
+            r#"{"!pkd-context":"fedi-e2ee:v1/api/actor/key-info","actor-id":"https://example.com/alice","created":"1722176511","inclusion-proof":["yWF2i5NqdyB9s0nEGnNQpoxSOcIwySMCch5xTOJurwE"],"key-id":"foo","merkle-root":"pkd-mr-v1:rZgQvJn16wkOuNq3ejHqC0zDkuQ-3GBpCR0YP6Xy5yQ","public-key":"ed25519:Tm2XBvb0mAb4ldVubCzvz0HMTczR8VGF44sv478VFLM","revoked":null,"revoke-root":null}"#,
+        );
+        assert!(info.revoked.is_none() && info.revoke_root.is_none());
+
+        let info: KeyInfo = parse(
+            r#"{"!pkd-context":"fedi-e2ee:v1/api/actor/key-info","actor-id":"https://example.com/alice","created":"1722176511","inclusion-proof":[],"key-id":"foo","merkle-root":"pkd-mr-v1:rZgQvJn16wkOuNq3ejHqC0zDkuQ-3GBpCR0YP6Xy5yQ","public-key":"ed25519:Tm2XBvb0mAb4ldVubCzvz0HMTczR8VGF44sv478VFLM","revoked":"1730902833","revoke-root":"pkd-mr-v1:HlRR_f1fFrRGu7Mczkdi41po07iP9JYjCp1GBb2y_nk"}"#,
+        );
+        assert_eq!(info.revoked, Some(Timestamp::from_secs(1730902833)));
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#get-apiactoractor_idauxiliary
+    //= type=test
+    //# The `!pkd-context` field will be set to the ASCII string `fedi-e2ee:v1/api/actor/aux-info`.
+    #[test]
+    fn actor_auxiliary() {
+        let aux: ActorAuxiliary = parse(
+            r#"{"!pkd-context":"fedi-e2ee:v1/api/actor/aux-info","actor-id":"https://example.com/alice","auxiliary":[{"aux-id":"XUUDSZSwIWsanCX9Dr4WH5g9p1_pTaK6hZymeISJI0A","aux-type":"age-v1","created":"1730902834"},{"aux-id":"qVca3ELZdRW2yGZj8kfbynFKPDrOCoKzFJlqwquzfDw","aux-type":"openssh","created":"1730902835"}]}"#,
+        );
+        assert_eq!(aux.auxiliary[1].aux_type, "openssh");
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#get-apiactoractor_idauxiliaryaux_data_id
+    //= type=test
+    //# The `!pkd-context` field will be set to the ASCII string `fedi-e2ee:v1/api/actor/get-aux`.
+    #[test]
+    fn aux_info() {
+        let aux: AuxInfo = parse(
+            r#"{"!pkd-context":"fedi-e2ee:v1/api/actor/get-aux","actor-id":"https://example.com/alice","aux-data":"age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p","aux-id":"XUUDSZSwIWsanCX9Dr4WH5g9p1_pTaK6hZymeISJI0A","aux-type":"age-v1","created":"1730902834","inclusion-proof":["O4BrUUtPYC3PWIr6uo0o8-zMrH4hT43WLmNrpUJTpKk","s-91v_WIcTNuyI-EbVTJs71Vq0iNbwj3dWN7akDj5jw","omGuBQqThwTt-hAFM7Pk_4Yx_21YNe8f8zX_Lxo9dpc"],"merkle-root":"pkd-mr-v1:KOspo1eBvXE9ZPyyNmW1sqqFeLqLA5f1LBCYHct1n9c","revoked":null,"revoke-root":null}"#,
+        );
+        assert_eq!(aux.aux_type, "age-v1");
+        assert_eq!(aux.inclusion_proof.len(), 3);
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#get-apihistory
+    //= type=test
+    //# The `!pkd-context` field will be set to the ASCII string `fedi-e2ee:v1/api/history`.
+    #[test]
+    fn history() {
+        let history: History = parse(
+            r#"{"!pkd-context":"fedi-e2ee:v1/api/history","current-time":"1730905988","created":"1601016659","merkle-root":"pkd-mr-v1:XINzPw6Z8ygzDQSZVpGtUmjVIqGVkkzWat_tkuWit3M"}"#,
+        );
+        assert_eq!(history.current_time, Timestamp::from_secs(1730905988));
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#get-apihistorysincelast_hash
+    //= type=test
+    //# The `!pkd-context` field will be set to the ASCII string `fedi-e2ee:v1/api/history/since`.
+    #[test]
+    fn history_since() {
+        let since: HistorySince = parse(
+            r#"{"!pkd-context":"fedi-e2ee:v1/api/history/since","current-time":"1730905988","records":[{"created":"1730905988","encrypted-message":"{}","message":{"action":"AddAuxData"},"merkle-root":"pkd-mr-v1:Io01AlF_FeRiJounhQjty3tsxEKHekPVTd7r_3BHpXc","rewrapped-keys":{"example.foo.bar":{"aux-type":"<hpke ciphertext goes here>"}}},{"created":"1730905989","encrypted-message":"{}","message":null,"merkle-root":"pkd-mr-v1:Io01AlF_FeRiJounhQjty3tsxEKHekPVTd7r_3BHpXc"}]}"#,
+        );
+        assert_eq!(since.records.len(), 2);
+        assert_eq!(
+            since.records[0].rewrapped_keys.as_ref().unwrap()["example.foo.bar"]["aux-type"],
+            "<hpke ciphertext goes here>"
+        );
+        assert!(since.records[1].message.is_none() && since.records[1].rewrapped_keys.is_none());
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#get-apihistoryviewhash
+    //= type=test
+    //# The `!pkd-context` field will be set to the ASCII string `fedi-e2ee:v1/api/history/view`.
+    #[test]
+    fn history_view() {
+        let view: HistoryView = parse(
+            r#"{"!pkd-context":"fedi-e2ee:v1/api/history/view","created":"1730905988","encrypted-message":"{}","inclusion-proof":["w3N3BU44g4MLC-sDGSzr1nJO9jmZfh_yq1bhpLjjWmo"],"message":null,"merkle-root":"pkd-mr-v1:Io01AlF_FeRiJounhQjty3tsxEKHekPVTd7r_3BHpXc","rewrapped-keys":null}"#,
+        );
+        assert_eq!(view.inclusion_proof.len(), 1);
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#get-apiextensions
+    //= type=test
+    //# The `!pkd-context` field will be set to the ASCII string `fedi-e2ee:v1/api/extensions`.
+    #[test]
+    fn extensions() {
+        // The spec's example, verbatim
+        let extensions: Extensions = parse(
+            r#"{
+  "!pkd-context": "fedi-e2ee:v1/api/extensions",
+  "time": "1731080850",
+  "extensions": [
+    {
+      "id": "foo-v1",
+      "version": "1.0.0",
+      "ref": "https://example.com/v1"
+    }
+  ]
+}"#,
+        );
+        assert_eq!(extensions.current_time, Timestamp::from_secs(1731080850));
+        let extension = &extensions.extensions[0];
+        assert_eq!(extension.id, "foo-v1");
+        assert_eq!(extension.reference, "https://example.com/v1");
+        assert!(extension.extra.is_empty());
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#get-apiextensions
+        //= type=test
+        //# Extensions **MAY** include optional additional fields, if necessary, in the above table.
+        let extensions: Extensions = parse(
+            r#"{"!pkd-context":"fedi-e2ee:v1/api/extensions","current-time":"1731080850","extensions":[{"id":"foo-v1","version":"1.0.0","ref":"https://example.com/v1","max-length":64}]}"#,
+        );
+        assert_eq!(extensions.extensions[0].extra["max-length"], 64);
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#get-apireplicas
+    //= type=test
+    //# The `!pkd-context` field will be set to the ASCII string `fedi-e2ee:v1/api/replicas`.
+    #[test]
+    fn replicas() {
+        // The spec's example, verbatim, which uses `time` and `extensions` rather than `current-time` and `replicas`
+        let replicas: Replicas = parse(
+            r#"{
+  "!pkd-context": "fedi-e2ee:v1/api/replicas",
+  "time": "1731080855",
+  "extensions": [
+    {
+      "id": "7k18At1PNkUmWokYbkpS5t29ZPWQASvg2dWXaFiOnac",
+      "ref": "https://example.org"
+    },
+    {
+      "id": "1jrlXOicceUMsxs1c5F1EbVPvy1CRWgH42k_UrMceQg",
+      "ref": "https://example.org"
+    }
+  ]
+}"#,
+        );
+        assert_eq!(replicas.current_time, Timestamp::from_secs(1731080855));
+        assert_eq!(replicas.replicas.len(), 2);
+        assert_eq!(
+            replicas.replicas[1].id,
+            "1jrlXOicceUMsxs1c5F1EbVPvy1CRWgH42k_UrMceQg"
+        );
+        assert_eq!(replicas.replicas[0].reference, "https://example.org");
+        assert!(replicas.replicas[0].extra.is_empty());
+
+        // The field names from the spec's table
+        let replicas: Replicas = parse(
+            r#"{"!pkd-context":"fedi-e2ee:v1/api/replicas","current-time":"1731080855","replicas":[]}"#,
+        );
+        assert!(replicas.replicas.is_empty());
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#get-apiserver-public-key
+    //= type=test
+    //# The `!pkd-context` field will be set to the ASCII string `fedi-e2ee:v1/api/server-public-key`.
+    #[test]
+    fn server_public_key() {
+        let key: ServerPublicKey = parse(
+            r#"{"!pkd-context":"fedi-e2ee:v1/api/server-public-key","current-time":"1730909831","hpke-ciphersuite":"Curve25519_SHA256_ChachaPoly","hpke-public-key":"3NtzCdMS1nuAVGHQStL-2evsgYz_LCuEzLeXXlrX7tM"}"#,
+        );
+        assert_eq!(key.hpke_ciphersuite, "Curve25519_SHA256_ChachaPoly");
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#post-apirevoke
+    //= type=test
+    //# The `!pkd-context` field will be set to the ASCII string `fedi-e2ee:v1/api/revoke`.
+    #[test]
+    fn revoke() {
+        let token = RevocationToken::issue(&crate::SigningKey::from_bytes(&[1; 32]));
+        let request = RevokeRequest::new(token.clone(), &Timestamp::from_secs(1730909831));
+        let request: RevokeRequest = parse(&serde_json::to_string(&request).unwrap());
+        assert_eq!(request.revocation_token, token);
+
+        let revoked: Revoked =
+            parse(r#"{"!pkd-context":"fedi-e2ee:v1/api/revoke","time":"1730909831"}"#);
+        assert_eq!(revoked.current_time, Timestamp::from_secs(1730909831));
+        let revoked: Revoked =
+            parse(r#"{"!pkd-context":"fedi-e2ee:v1/api/revoke","current-time":"1730909831"}"#);
+        assert_eq!(revoked.current_time, Timestamp::from_secs(1730909831));
+    }
+}
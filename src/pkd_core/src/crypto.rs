@@ -0,0 +1,569 @@
+//! PKD v1 cryptography protocols
+//!
+//! See [Cryptography Protocols](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#cryptography-protocols)
+
+use aes::cipher::{KeyIvInit, StreamCipher};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha512};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+use crate::{MerkleRoot, PublicKey, action::SymmetricKey, utils::Encrypted};
+
+//= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#version-1-constants
+//# These constants are mostly used for domain separation.
+const VERSION: u8 = 0x01;
+const KDF_ENCRYPT_KEY: &[u8] = b"FediE2EE-v1-Compliance-Encryption-Key";
+const KDF_AUTH_KEY: &[u8] = b"FediE2EE-v1-Compliance-Message-Auth-Key";
+const KDF_COMMIT_SALT: &[u8] = b"FediE2EE-v1-Compliance-KDF-Salt";
+
+/// Length of the random value `r` in an encrypted attribute
+const RANDOM_LEN: usize = 32;
+/// Length of the plaintext commitment `Q` in an encrypted attribute
+const COMMITMENT_LEN: usize = 32;
+/// Length of the truncated authentication tag `t` in an encrypted attribute
+const TAG_LEN: usize = 32;
+/// Length of `h || r || Q || t`
+const HEADER_LEN: usize = 1 + RANDOM_LEN + COMMITMENT_LEN + TAG_LEN;
+
+type Stream = ctr::Ctr128BE<aes::Aes256>;
+type Kdf = Hkdf<Sha512>;
+type HmacSha512 = Hmac<Sha512>;
+
+/// Errors that can occur while running PKD cryptography protocols
+#[derive(Debug, thiserror::Error)]
+pub enum CryptoError {
+    /// The operating system's random number generator failed
+    #[error("failed to gather randomness from the OS: {0}")]
+    Random(#[from] getrandom::Error),
+    /// Argon2id failed to compute the plaintext commitment
+    #[error("failed to compute plaintext commitment: {0}")]
+    Commitment(argon2::Error),
+    /// The ciphertext is too short to contain its header
+    #[error("ciphertext is too short, expected at least {expected} bytes found {found}")]
+    Truncated {
+        /// The minimum length of a ciphertext
+        expected: usize,
+        /// The length of the ciphertext
+        found: usize,
+    },
+    /// The ciphertext was made with an unsupported algorithm suite version
+    #[error("unsupported algorithm suite version {0:#04x}")]
+    UnsupportedVersion(u8),
+    /// The authentication tag doesn't match the ciphertext
+    #[error("invalid authentication tag")]
+    InvalidTag,
+    /// The plaintext commitment doesn't match the decrypted plaintext
+    #[error("invalid plaintext commitment")]
+    InvalidCommitment,
+    /// The decrypted plaintext isn't a valid value for the attribute
+    #[error("decrypted plaintext is not a valid attribute value")]
+    InvalidPlaintext,
+    /// There's no symmetric key for an encrypted attribute
+    #[error("missing symmetric key for attribute '{0}'")]
+    MissingKey(String),
+}
+
+/// A value that can be stored as an encrypted message attribute.
+///
+/// This is the plaintext that gets fed into the [message attribute encryption algorithm](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#message-attribute-encryption-algorithm).
+pub trait AttributeValue: Sized {
+    /// Return the plaintext bytes for this attribute
+    fn to_attribute_bytes(&self) -> Vec<u8>;
+    /// Parse the plaintext bytes of this attribute, returning [`None`] if they're invalid
+    fn from_attribute_bytes(bytes: Vec<u8>) -> Option<Self>;
+}
+
+impl AttributeValue for String {
+    fn to_attribute_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_attribute_bytes(bytes: Vec<u8>) -> Option<Self> {
+        String::from_utf8(bytes).ok()
+    }
+}
+
+impl AttributeValue for PublicKey {
+    fn to_attribute_bytes(&self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+
+    fn from_attribute_bytes(bytes: Vec<u8>) -> Option<Self> {
+        std::str::from_utf8(&bytes).ok()?.parse().ok()
+    }
+}
+
+impl AttributeValue for Vec<u8> {
+    fn to_attribute_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn from_attribute_bytes(bytes: Vec<u8>) -> Option<Self> {
+        Some(bytes)
+    }
+}
+
+/// A field of a message that's encrypted as a single attribute
+pub(crate) trait AttributeField: Sized {
+    /// The encrypted form of this field
+    type CipherText;
+    /// The key(s) used to encrypt this field
+    type Key;
+
+    /// Encrypt this field as `attribute` using a freshly generated key
+    fn encrypt_field(
+        &self,
+        attribute: &str,
+        root: &MerkleRoot,
+    ) -> Result<(Self::CipherText, Self::Key), CryptoError>;
+
+    /// Decrypt this field from `cipher` using `key`
+    fn decrypt_field(
+        cipher: &Self::CipherText,
+        key: &Self::Key,
+        attribute: &str,
+        root: &MerkleRoot,
+    ) -> Result<Self, CryptoError>;
+}
+
+impl<P: AttributeValue> AttributeField for P {
+    type CipherText = Encrypted<P>;
+    type Key = SymmetricKey;
+
+    fn encrypt_field(
+        &self,
+        attribute: &str,
+        root: &MerkleRoot,
+    ) -> Result<(Self::CipherText, Self::Key), CryptoError> {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-shreddability
+        //# Every Message will have a unique 256-bit random key per sensitive attribute.
+        let key = SymmetricKey::generate()?;
+        let cipher = Encrypted::encrypt(attribute, self, &key, root)?;
+        Ok((cipher, key))
+    }
+
+    fn decrypt_field(
+        cipher: &Self::CipherText,
+        key: &Self::Key,
+        attribute: &str,
+        root: &MerkleRoot,
+    ) -> Result<Self, CryptoError> {
+        cipher.decrypt(attribute, key, root)
+    }
+}
+
+impl<P: AttributeValue> AttributeField for Option<P> {
+    type CipherText = Option<Encrypted<P>>;
+    type Key = Option<SymmetricKey>;
+
+    fn encrypt_field(
+        &self,
+        attribute: &str,
+        root: &MerkleRoot,
+    ) -> Result<(Self::CipherText, Self::Key), CryptoError> {
+        match self {
+            Some(plain) => {
+                let (cipher, key) = plain.encrypt_field(attribute, root)?;
+                Ok((Some(cipher), Some(key)))
+            }
+            None => Ok((None, None)),
+        }
+    }
+
+    fn decrypt_field(
+        cipher: &Self::CipherText,
+        key: &Self::Key,
+        attribute: &str,
+        root: &MerkleRoot,
+    ) -> Result<Self, CryptoError> {
+        match (cipher, key) {
+            (Some(cipher), Some(key)) => cipher.decrypt(attribute, key, root).map(Some),
+            (None, _) => Ok(None),
+            (Some(_), None) => Err(CryptoError::MissingKey(attribute.to_string())),
+        }
+    }
+}
+
+/// `len(x)` as defined by the spec, the little-endian encoding of the length of `x` as a 64-bit integer.
+fn len(x: &[u8]) -> [u8; 8] {
+    (x.len() as u64).to_le_bytes()
+}
+
+/// PASETO's [PAE()](https://github.com/paseto-standard/paseto-spec/blob/master/docs/01-Protocol-Versions/Common.md#pae-definition)
+pub(crate) fn pae(pieces: &[&[u8]]) -> Vec<u8> {
+    fn le64(n: usize) -> [u8; 8] {
+        // PAE clears the most significant bit to remain compatible with signed 64-bit integers
+        ((n as u64) & (u64::MAX >> 1)).to_le_bytes()
+    }
+
+    let mut out = Vec::with_capacity(8 + pieces.iter().map(|p| 8 + p.len()).sum::<usize>());
+    out.extend_from_slice(&le64(pieces.len()));
+    for piece in pieces {
+        out.extend_from_slice(&le64(piece.len()));
+        out.extend_from_slice(piece);
+    }
+    out
+}
+
+/// Derive the AES-256-CTR key `Ek` and nonce `n`
+fn derive_stream(ikm: &[u8], h: u8, r: &[u8], a: &[u8]) -> Stream {
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-encryption-algorithm
+    //# Derive an encryption key, `Ek`, and nonce, `n`, through [`KDF`](#version-1-functions) with a NULL salt and an info
+    //# string set to `KDF_ENCRYPT_KEY || h || r || len(a) || a`, with an output length of 384 bits. The most significant
+    //# 256 bits will be the encryption key, `Ek`, while the remaining 128 bits will be the nonce, `n`.
+    let mut okm = Zeroizing::new([0; 48]);
+    Kdf::new(None, ikm)
+        .expand_multi_info(&[KDF_ENCRYPT_KEY, &[h], r, &len(a), a], okm.as_mut())
+        .expect("384 bits to be a valid HKDF-SHA512 output length");
+    let (ek, n) = okm.split_at(32);
+    Stream::new(ek.into(), n.into())
+}
+
+/// Derive the HMAC-SHA512 authentication key `Ak`
+fn derive_auth_key(ikm: &[u8], h: u8, r: &[u8], a: &[u8]) -> Zeroizing<[u8; 32]> {
+    let mut ak = Zeroizing::new([0; 32]);
+    Kdf::new(None, ikm)
+        .expand_multi_info(&[KDF_AUTH_KEY, &[h], r, &len(a), a], ak.as_mut())
+        .expect("256 bits to be a valid HKDF-SHA512 output length");
+    ak
+}
+
+/// Derive the commitment salt `s`
+fn derive_commit_salt(h: u8, r: &[u8], m: &[u8], a: &[u8]) -> [u8; 16] {
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-encryption-algorithm
+    //# Derive a commitment salt, `s`, as the [`Hash`](#version-1-functions) of
+    //# `KDF_COMMIT_SALT || h || r || len(m) || m || len(a) || a`
+    //# truncated to 128 bits (big endian / the least significant bits).
+    let hash = Sha512::new()
+        .chain_update(KDF_COMMIT_SALT)
+        .chain_update([h])
+        .chain_update(r)
+        .chain_update(len(m))
+        .chain_update(m)
+        .chain_update(len(a))
+        .chain_update(a)
+        .finalize();
+    let mut s = [0; 16];
+    s.copy_from_slice(&hash[hash.len() - 16..]);
+    s
+}
+
+/// Compute the [plaintext commitment](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#message-attribute-plaintext-commitment-algorithm) `Q`
+fn commit(a: &[u8], p: &[u8], m: &[u8], s: &[u8]) -> Result<[u8; COMMITMENT_LEN], CryptoError> {
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-plaintext-commitment-algorithm
+    //# Set `l` to `len(m) || m || len(a) || a || len(p) || p`.
+    let mut l = Zeroizing::new(Vec::with_capacity(24 + m.len() + a.len() + p.len()));
+    for piece in [m, a, p] {
+        l.extend_from_slice(&len(piece));
+        l.extend_from_slice(piece);
+    }
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#version-1-functions
+    //# | PwKDF         | Argon2id                | mem = 16MiB, iter = 3, para = 1                |
+    let params = argon2::Params::new(16 * 1024, 3, 1, Some(COMMITMENT_LEN))
+        .map_err(CryptoError::Commitment)?;
+    let mut q = [0; COMMITMENT_LEN];
+    argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+        .hash_password_into(&l, s, &mut q)
+        .map_err(CryptoError::Commitment)?;
+    Ok(q)
+}
+
+/// Compute the truncated authentication tag `t`
+fn tag(ak: &[u8], h: u8, r: &[u8], a: &[u8], c: &[u8], q: &[u8]) -> HmacSha512 {
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-encryption-algorithm
+    //# Calculate the [`MAC`](#version-1-functions) of `h || r || len(a) || a || len(c) || c || len(Q) || Q`, with `Ak` as
+    //# the key.
+    let mut mac = HmacSha512::new_from_slice(ak).expect("HMAC to accept keys of any size");
+    mac.update(&[h]);
+    mac.update(r);
+    for piece in [a, c, q] {
+        mac.update(&len(piece));
+        mac.update(piece);
+    }
+    mac
+}
+
+impl<P: AttributeValue> Encrypted<P> {
+    /// Encrypt `plaintext` as the message attribute named `attribute`.
+    ///
+    /// `root` is the `recent-merkle-root` of the message this attribute is going to be sent in.
+    ///
+    /// See [Message Attribute Encryption Algorithm](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#message-attribute-encryption-algorithm)
+    pub fn encrypt(
+        attribute: &str,
+        plaintext: &P,
+        key: &SymmetricKey,
+        root: &MerkleRoot,
+    ) -> Result<Self, CryptoError> {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-encryption-algorithm
+        //# Generate 32 bytes of random data, `r`.
+        let mut r = [0; RANDOM_LEN];
+        getrandom::fill(&mut r)?;
+        Self::encrypt_with_random(attribute, plaintext, key, root, r)
+    }
+
+    /// Like [`Encrypted::encrypt`], but with the random value `r` already chosen.
+    pub(crate) fn encrypt_with_random(
+        attribute: &str,
+        plaintext: &P,
+        key: &SymmetricKey,
+        root: &MerkleRoot,
+        r: [u8; RANDOM_LEN],
+    ) -> Result<Self, CryptoError> {
+        let ikm = key.0.expose_secret();
+        let a = attribute.as_bytes();
+        let m = root.0.as_ref();
+        let p = Zeroizing::new(plaintext.to_attribute_bytes());
+        let h = VERSION;
+
+        let s = derive_commit_salt(h, &r, m, a);
+        let q = commit(a, &p, m, &s)?;
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-encryption-algorithm
+        //# Encrypt the plaintext attribute using [`Stream`](#version-1-functions), with the nonce set to `n`, to obtain the
+        //# ciphertext, `c`.
+        let mut c = p.to_vec();
+        derive_stream(ikm, h, &r, a).apply_keystream(&mut c);
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-encryption-algorithm
+        //# Truncate the HMAC output to the rightmost 32 bytes (256 bits) to obtain `t`.
+        let ak = derive_auth_key(ikm, h, &r, a);
+        let full = tag(ak.as_ref(), h, &r, a, &c, &q).finalize().into_bytes();
+        let t = &full[full.len() - TAG_LEN..];
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-encryption-algorithm
+        //# Return `h || r || Q || t || c`.
+        let mut out = Vec::with_capacity(HEADER_LEN + c.len());
+        out.push(h);
+        out.extend_from_slice(&r);
+        out.extend_from_slice(&q);
+        out.extend_from_slice(t);
+        out.extend_from_slice(&c);
+        Ok(Self::from_ciphertext(out))
+    }
+
+    /// Decrypt this message attribute named `attribute`.
+    ///
+    /// `root` is the `recent-merkle-root` of the message this attribute was sent in.
+    ///
+    /// See [Message Attribute Decryption Algorithm](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#message-attribute-decryption-algorithm)
+    pub fn decrypt(
+        &self,
+        attribute: &str,
+        key: &SymmetricKey,
+        root: &MerkleRoot,
+    ) -> Result<P, CryptoError> {
+        let ikm = key.0.expose_secret();
+        let a = attribute.as_bytes();
+        let m = root.0.as_ref();
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-decryption-algorithm
+        //# Decompose input 2 into `h`, `r`, `Q`, `t`, and `c`.
+        if self.len() < HEADER_LEN {
+            return Err(CryptoError::Truncated {
+                expected: HEADER_LEN,
+                found: self.len(),
+            });
+        }
+        let (h, rest) = self.split_at(1);
+        let (r, rest) = rest.split_at(RANDOM_LEN);
+        let (q, rest) = rest.split_at(COMMITMENT_LEN);
+        let (t, c) = rest.split_at(TAG_LEN);
+        let h = h[0];
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-decryption-algorithm
+        //# Ensure `h` is equal to the expected version prefix ([`VERSION`](#version-1-constants) currently).
+        //# If it is not, return a decryption error.
+        if h != VERSION {
+            return Err(CryptoError::UnsupportedVersion(h));
+        }
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-decryption-algorithm
+        //# Compare `t` with `t2`, using a [constant-time compare operation](https://soatok.blog/2020/08/27/soatoks-guide-to-side-channel-attacks/#string-comparison).
+        //# If the two are not equal, return a decryption error.
+        let ak = derive_auth_key(ikm, h, r, a);
+        tag(ak.as_ref(), h, r, a, c, q)
+            .verify_truncated_right(t)
+            .map_err(|_| CryptoError::InvalidTag)?;
+
+        let mut p = Zeroizing::new(c.to_vec());
+        derive_stream(ikm, h, r, a).apply_keystream(&mut p);
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-decryption-algorithm
+        //# Compare `Q` with `Q2` using a [constant-time compare operation](https://soatok.blog/2020/08/27/soatoks-guide-to-side-channel-attacks/#string-comparison).
+        //# If the two are not equal, return a decryption error.
+        let s = derive_commit_salt(h, r, m, a);
+        let q2 = commit(a, &p, m, &s)?;
+        if !bool::from(q.ct_eq(&q2)) {
+            return Err(CryptoError::InvalidCommitment);
+        }
+
+        P::from_attribute_bytes(std::mem::take(&mut *p)).ok_or(CryptoError::InvalidPlaintext)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        MerkleRoot, PublicKey, action::SymmetricKey, crypto::CryptoError, utils::Encrypted,
+    };
+
+    const ROOT: MerkleRoot = MerkleRoot::new([0xab; 32]);
+    const ACTOR: &str = "https://example.com/users/alice";
+
+    fn key() -> SymmetricKey {
+        SymmetricKey::init(|v| v.extend_from_slice(&[0x42; 32]))
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-encryption-algorithm
+    //= type=test
+    //# Return `h || r || Q || t || c`.
+    #[test]
+    fn encrypt_layout() {
+        let r = [0x07; 32];
+        let enc =
+            Encrypted::encrypt_with_random("actor", &ACTOR.to_string(), &key(), &ROOT, r).unwrap();
+        assert_eq!(enc.len(), 1 + 32 + 32 + 32 + ACTOR.len());
+        assert_eq!(enc[0], 0x01);
+        assert_eq!(&enc[1..33], &r);
+        assert_ne!(&enc[97..], ACTOR.as_bytes());
+    }
+
+    /// Computed independently from the spec's algorithm description with Python's `cryptography` package, pinning the
+    /// HKDF info strings, the commitment salt and the truncated MAC.
+    #[test]
+    fn encrypt_known_answer() {
+        fn hex(bytes: &[u8]) -> String {
+            bytes.iter().map(|b| format!("{b:02x}")).collect()
+        }
+
+        let r = [0x07; 32];
+        let enc =
+            Encrypted::encrypt_with_random("actor", &ACTOR.to_string(), &key(), &ROOT, r).unwrap();
+        assert_eq!(enc[0], 0x01);
+        assert_eq!(&enc[1..33], &r);
+        assert_eq!(
+            hex(&enc[33..65]),
+            "23bbe124ff19cb03fd514349205a6cb0f91b8cfd76583741b5eaf144365fcc1c"
+        );
+        assert_eq!(
+            hex(&enc[65..97]),
+            "07654d4dc261c763a77a5cf309ae5eb162ed2ccd8623149e3db4d035d6ab66de"
+        );
+        assert_eq!(
+            hex(&enc[97..]),
+            "1622d705e997c7e22ea81de03d8f7efd21216f5ab913949c59f5ffd67a8725"
+        );
+        assert_eq!(enc.decrypt("actor", &key(), &ROOT).unwrap(), ACTOR);
+    }
+
+    #[test]
+    fn encrypt_deterministic_for_fixed_random() {
+        let r = [0x07; 32];
+        let pk = PublicKey::new([0x11; 32]);
+        let a = Encrypted::encrypt_with_random("public-key", &pk, &key(), &ROOT, r).unwrap();
+        let b = Encrypted::encrypt_with_random("public-key", &pk, &key(), &ROOT, r).unwrap();
+        assert_eq!(a, b);
+
+        // Domain separated by attribute name and merkle root
+        let c = Encrypted::encrypt_with_random("actor", &pk, &key(), &ROOT, r).unwrap();
+        assert_ne!(a[33..], c[33..]);
+        let d =
+            Encrypted::encrypt_with_random("public-key", &pk, &key(), &MerkleRoot::default(), r)
+                .unwrap();
+        assert_ne!(a[33..65], d[33..65]);
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-decryption-algorithm
+    //= type=test
+    //# Return `p`.
+    #[test]
+    fn decrypt_roundtrip() {
+        let actor = ACTOR.to_string();
+        let enc = Encrypted::encrypt("actor", &actor, &key(), &ROOT).unwrap();
+        assert_eq!(enc.decrypt("actor", &key(), &ROOT).unwrap(), actor);
+
+        let pk = PublicKey::new([0x11; 32]);
+        let enc = Encrypted::encrypt("public-key", &pk, &key(), &ROOT).unwrap();
+        assert_eq!(enc.decrypt("public-key", &key(), &ROOT).unwrap(), pk);
+
+        let data = vec![0x00, 0xff, 0x10];
+        let enc = Encrypted::encrypt("aux-data", &data, &key(), &ROOT).unwrap();
+        assert_eq!(enc.decrypt("aux-data", &key(), &ROOT).unwrap(), data);
+    }
+
+    #[test]
+    fn decrypt_rejects() {
+        let actor = ACTOR.to_string();
+        let enc = Encrypted::encrypt("actor", &actor, &key(), &ROOT).unwrap();
+
+        // Truncated
+        let short = Encrypted::<String>::from_ciphertext(enc[..96].to_vec());
+        assert!(matches!(
+            short.decrypt("actor", &key(), &ROOT),
+            Err(CryptoError::Truncated { found: 96, .. })
+        ));
+
+        // Wrong version
+        let mut bytes = enc.clone().into_inner();
+        bytes[0] = 0x02;
+        assert!(matches!(
+            Encrypted::<String>::from_ciphertext(bytes).decrypt("actor", &key(), &ROOT),
+            Err(CryptoError::UnsupportedVersion(0x02))
+        ));
+
+        // Tampered ciphertext
+        let mut bytes = enc.clone().into_inner();
+        *bytes.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            Encrypted::<String>::from_ciphertext(bytes).decrypt("actor", &key(), &ROOT),
+            Err(CryptoError::InvalidTag)
+        ));
+
+        // Wrong key or attribute
+        let other = SymmetricKey::init(|v| v.extend_from_slice(&[0x43; 32]));
+        assert!(matches!(
+            enc.decrypt("actor", &other, &ROOT),
+            Err(CryptoError::InvalidTag)
+        ));
+        assert!(matches!(
+            enc.decrypt("operator", &key(), &ROOT),
+            Err(CryptoError::InvalidTag)
+        ));
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-attribute-decryption-algorithm
+        //= type=test
+        //# Compare `Q` with `Q2` using a [constant-time compare operation](https://soatok.blog/2020/08/27/soatoks-guide-to-side-channel-attacks/#string-comparison).
+        //# If the two are not equal, return a decryption error.
+        assert!(matches!(
+            enc.decrypt("actor", &key(), &MerkleRoot::default()),
+            Err(CryptoError::InvalidCommitment)
+        ));
+    }
+
+    #[test]
+    fn pae() {
+        use super::pae;
+
+        assert_eq!(pae(&[]), b"\x00\x00\x00\x00\x00\x00\x00\x00");
+        assert_eq!(
+            pae(&[b""]),
+            b"\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00"
+        );
+        assert_eq!(
+            pae(&[b"test"]),
+            b"\x01\x00\x00\x00\x00\x00\x00\x00\x04\x00\x00\x00\x00\x00\x00\x00test"
+        );
+    }
+
+    #[test]
+    fn encrypt_random() {
+        let data = vec![0xde, 0xad, 0xbe, 0xef];
+        let a = Encrypted::encrypt("aux-data", &data, &key(), &ROOT).unwrap();
+        let b = Encrypted::encrypt("aux-data", &data, &key(), &ROOT).unwrap();
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,350 @@
+//! [Protocol Message Encryption](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#protocol-message-encryption) with HPKE
+
+use ::hpke::{Deserializable, Kem as _, OpModeR, OpModeS, Serializable};
+use rand_core::{OsRng, TryRngCore};
+use secrecy::ExposeSecret;
+
+use crate::{CryptoError, ProtocolContext, ProtocolMessage, action::Action};
+
+/// HPKE KEM for the `Curve25519_SHA256_ChachaPoly` cipher suite
+type Kem = ::hpke::kem::X25519HkdfSha256;
+/// HPKE KDF for the `Curve25519_SHA256_ChachaPoly` cipher suite
+type Kdf = ::hpke::kdf::HkdfSha256;
+/// HPKE AEAD for the `Curve25519_SHA256_ChachaPoly` cipher suite
+type Aead = ::hpke::aead::ChaCha20Poly1305;
+
+/// Length of an encapsulated X25519 key
+const ENCAPPED_LEN: usize = 32;
+
+//= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#protocol-message-encryption
+//# Users **MAY** pad the plaintext before encryption with additional whitespace to their desired length, but **SHOULD**
+//# keep their plaintext JSON blobs smaller than 16 MiB (16,777,216 bytes).
+/// The largest plaintext protocol message we'll encrypt
+pub const MAX_PLAINTEXT_LEN: usize = 16 * 1024 * 1024;
+
+/// Errors from encrypting a [`ProtocolMessage`]
+#[derive(Debug, thiserror::Error)]
+pub enum MessageEncryptionError {
+    /// [`Action::BurnDown`] messages must be sent in plaintext
+    #[error("BurnDown messages must not be encrypted")]
+    BurnDown,
+    /// The plaintext is larger than [`MAX_PLAINTEXT_LEN`]
+    #[error("plaintext is too large, expected at most {MAX_PLAINTEXT_LEN} bytes found {0}")]
+    TooLarge(usize),
+    /// The HPKE public key isn't a valid X25519 key
+    #[error("invalid HPKE public key")]
+    InvalidPublicKey,
+    /// The message couldn't be serialized
+    #[error("failed to serialize message: {0}")]
+    Serialize(#[from] serde_json::Error),
+    /// HPKE failed to seal the message
+    #[error("HPKE failure: {0}")]
+    Hpke(::hpke::HpkeError),
+}
+
+/// Errors from decrypting an [`EncryptedMessage`]
+#[derive(Debug, thiserror::Error)]
+pub enum MessageDecryptionError {
+    /// The ciphertext is too short to hold an encapsulated key
+    #[error("ciphertext is truncated, expected at least {ENCAPPED_LEN} bytes found {0}")]
+    Truncated(usize),
+    /// The ciphertext failed to authenticate
+    #[error("failed to decrypt message")]
+    Decrypt,
+    /// The plaintext isn't valid UTF-8
+    #[error("decrypted message isn't UTF-8")]
+    Utf8(#[from] std::string::FromUtf8Error),
+    /// The plaintext isn't a valid [`ProtocolMessage`]
+    #[error("failed to parse decrypted message: {0}")]
+    Parse(#[from] serde_json::Error),
+    /// The plaintext is an [`Action::BurnDown`], which must never be encrypted
+    #[error("BurnDown messages must not be encrypted")]
+    BurnDown,
+}
+
+/// A Public Key Directory's X25519 public key for HPKE, encoded in unpadded [`base64url`](https://datatracker.ietf.org/doc/html/rfc4648#section-5)
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct HpkePublicKey(pub [u8; 32]);
+
+impl serde::Serialize for HpkePublicKey {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::utils::serde_base64::serialize(&self.0, serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for HpkePublicKey {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = crate::utils::serde_base64::deserialize(deserializer)?;
+        bytes.try_into().map(Self).map_err(|bytes: Vec<u8>| {
+            <D::Error as serde::de::Error>::invalid_length(bytes.len(), &"a 32 byte public key")
+        })
+    }
+}
+
+/// A Public Key Directory's X25519 secret key for HPKE
+#[derive(Debug)]
+pub struct HpkeSecretKey(secrecy::SecretBox<[u8; 32]>);
+
+impl HpkeSecretKey {
+    /// Create a [`HpkeSecretKey`] from its raw bytes
+    pub fn from_bytes(bytes: &[u8; 32]) -> Self {
+        Self(secrecy::SecretBox::new(Box::new(*bytes)))
+    }
+
+    /// Generate a fresh [`HpkeSecretKey`] using the OS's random number generator
+    pub fn generate() -> Result<Self, CryptoError> {
+        let mut bytes = secrecy::SecretBox::new(Box::new([0; 32]));
+        getrandom::fill(secrecy::ExposeSecretMut::expose_secret_mut(&mut bytes))?;
+        Ok(Self(bytes))
+    }
+
+    /// The [`HpkePublicKey`] messages should be encrypted to
+    pub fn public_key(&self) -> HpkePublicKey {
+        let pk = Kem::sk_to_pk(&self.to_hpke());
+        HpkePublicKey(pk.to_bytes().into())
+    }
+
+    fn to_hpke(&self) -> <Kem as ::hpke::Kem>::PrivateKey {
+        // 32 byte X25519 secret keys are always valid, they're clamped when used
+        <Kem as ::hpke::Kem>::PrivateKey::from_bytes(self.0.expose_secret())
+            .expect("X25519 secret keys are 32 bytes")
+    }
+}
+
+/// A [`ProtocolMessage`] encrypted to a Public Key Directory with HPKE.
+///
+/// The ciphertext is the encapsulated key followed by the sealed message.
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EncryptedMessage {
+    /// Domain separation for the protocol version, also used as the HPKE AAD
+    #[serde(rename = "!pkd-context")]
+    pub context: ProtocolContext,
+    /// The HPKE ciphertext
+    #[serde(with = "crate::utils::serde_base64")]
+    pub encrypted_message: Vec<u8>,
+}
+
+impl ProtocolMessage {
+    /// Encrypt this message to the Public Key Directory's HPKE public key.
+    ///
+    /// If `pad_to` is given, the plaintext JSON is padded with trailing whitespace up to that many bytes.
+    ///
+    /// See [Protocol Message Encryption](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#protocol-message-encryption)
+    pub fn encrypt(
+        &self,
+        key: &HpkePublicKey,
+        pad_to: Option<usize>,
+    ) -> Result<EncryptedMessage, MessageEncryptionError> {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#protocol-message-encryption
+        //# `BurnDown` messages **MUST NOT** be encrypted.
+        if let Action::BurnDown { .. } = self.action {
+            return Err(MessageEncryptionError::BurnDown);
+        }
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#protocol-message-encryption
+        //# When encryption is chosen, the Protocol Message **MUST** be serialized as a JSON string and then encrypted according to
+        //# the specific HPKE cipher suite advertised by the Public Key Directory, using the given public key.
+        let mut plaintext = self.to_json()?.into_bytes();
+        if let Some(len) = pad_to
+            && len > plaintext.len()
+        {
+            plaintext.resize(len, b' ');
+        }
+        if plaintext.len() > MAX_PLAINTEXT_LEN {
+            return Err(MessageEncryptionError::TooLarge(plaintext.len()));
+        }
+
+        EncryptedMessage::seal(&plaintext, key)
+    }
+}
+
+impl EncryptedMessage {
+    /// Seal a serialized protocol message to `key`
+    fn seal(plaintext: &[u8], key: &HpkePublicKey) -> Result<Self, MessageEncryptionError> {
+        let context = ProtocolContext::new();
+        let key = <Kem as ::hpke::Kem>::PublicKey::from_bytes(&key.0)
+            .map_err(|_| MessageEncryptionError::InvalidPublicKey)?;
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#protocol-message-encryption
+        //# When encrypting, the AAD parameter of the HPKE encryption **MUST** be set to the value of the `!pkd-context` field.
+        let (encapped, ciphertext) = ::hpke::single_shot_seal::<Aead, Kdf, Kem, _>(
+            &OpModeS::Base,
+            &key,
+            &[],
+            plaintext,
+            context.as_str().as_bytes(),
+            &mut OsRng.unwrap_err(),
+        )
+        .map_err(MessageEncryptionError::Hpke)?;
+
+        let mut encrypted_message = Vec::with_capacity(ENCAPPED_LEN + ciphertext.len());
+        encrypted_message.extend_from_slice(&encapped.to_bytes());
+        encrypted_message.extend_from_slice(&ciphertext);
+        Ok(Self {
+            context,
+            encrypted_message,
+        })
+    }
+
+    /// Decrypt this message with the Public Key Directory's HPKE secret key.
+    ///
+    /// See [Protocol Message Decryption](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#protocol-message-decryption)
+    pub fn decrypt(&self, key: &HpkeSecretKey) -> Result<ProtocolMessage, MessageDecryptionError> {
+        let len = self.encrypted_message.len();
+        if len < ENCAPPED_LEN {
+            return Err(MessageDecryptionError::Truncated(len));
+        }
+        let (encapped, ciphertext) = self.encrypted_message.split_at(ENCAPPED_LEN);
+        let encapped = <Kem as ::hpke::Kem>::EncappedKey::from_bytes(encapped)
+            .map_err(|_| MessageDecryptionError::Decrypt)?;
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#protocol-message-decryption
+        //# Ensure the AAD parameter is set to the value of the `!pkd-context` field.
+        let plaintext = ::hpke::single_shot_open::<Aead, Kdf, Kem>(
+            &OpModeR::Base,
+            &key.to_hpke(),
+            &encapped,
+            &[],
+            ciphertext,
+            self.context.as_str().as_bytes(),
+        )
+        .map_err(|_| MessageDecryptionError::Decrypt)?;
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#protocol-message-decryption
+        //# The result of a successful decryption **MUST** be a string that corresponds to a JSON-encoded Protocol Message. This
+        //# JSON blob **MAY** have additional whitespace appended to it.
+        let plaintext = String::from_utf8(plaintext)?;
+        let message =
+            ProtocolMessage::from_json(plaintext.trim_end_matches([' ', '\t', '\n', '\r']))?;
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#protocol-message-encryption
+        //# `BurnDown` messages **MUST NOT** be encrypted.
+        if let Action::BurnDown { .. } = message.action {
+            return Err(MessageDecryptionError::BurnDown);
+        }
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        EncryptedMessage, HpkePublicKey, HpkeSecretKey, MessageDecryptionError,
+        MessageEncryptionError,
+    };
+    use crate::{
+        MerkleRoot, ProtocolMessage, Timestamped,
+        action::{Action, BurnDown, CipherText, SymmetricKey},
+        message::checkpoint,
+        utils::Encrypted,
+    };
+
+    // X25519 base point, a valid public key
+    const KEY: HpkePublicKey = HpkePublicKey([
+        9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0,
+    ]);
+
+    #[test]
+    fn encrypt_padded() {
+        let msg = checkpoint();
+        let len = msg.to_json().unwrap().len();
+
+        let enc = msg.encrypt(&KEY, None).unwrap();
+        // encapsulated key || ciphertext || tag
+        assert_eq!(enc.encrypted_message.len(), 32 + len + 16);
+
+        let padded = msg.encrypt(&KEY, Some(4096)).unwrap();
+        assert_eq!(padded.encrypted_message.len(), 32 + 4096 + 16);
+
+        let json = serde_json::to_value(&padded).unwrap();
+        assert_eq!(
+            json["!pkd-context"],
+            "https://github.com/fedi-e2ee/public-key-directory/v1"
+        );
+        assert!(json["encrypted-message"].is_string());
+    }
+
+    #[test]
+    fn decrypt_roundtrip() {
+        let sk = HpkeSecretKey::generate().unwrap();
+        let msg = checkpoint();
+        for pad_to in [None, Some(1024)] {
+            let enc = msg.encrypt(&sk.public_key(), pad_to).unwrap();
+            let json = serde_json::to_string(&enc).unwrap();
+            let enc: EncryptedMessage = serde_json::from_str(&json).unwrap();
+            assert_eq!(enc.decrypt(&sk).unwrap(), msg);
+        }
+    }
+
+    #[test]
+    fn decrypt_rejects() {
+        let sk = HpkeSecretKey::from_bytes(&[1; 32]);
+        let other = HpkeSecretKey::from_bytes(&[2; 32]);
+        let mut enc = checkpoint().encrypt(&sk.public_key(), None).unwrap();
+        assert!(matches!(
+            enc.decrypt(&other),
+            Err(MessageDecryptionError::Decrypt)
+        ));
+
+        let last = enc.encrypted_message.len() - 1;
+        enc.encrypted_message[last] ^= 1;
+        assert!(matches!(
+            enc.decrypt(&sk),
+            Err(MessageDecryptionError::Decrypt)
+        ));
+
+        enc.encrypted_message.truncate(31);
+        assert!(matches!(
+            enc.decrypt(&sk),
+            Err(MessageDecryptionError::Truncated(31))
+        ));
+
+        let enc = EncryptedMessage::seal(b"{} garbage", &sk.public_key()).unwrap();
+        assert!(matches!(
+            enc.decrypt(&sk),
+            Err(MessageDecryptionError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn encrypt_too_large() {
+        assert!(matches!(
+            checkpoint().encrypt(&KEY, Some(super::MAX_PLAINTEXT_LEN + 1)),
+            Err(MessageEncryptionError::TooLarge(_))
+        ));
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#protocol-message-encryption
+    //= type=test
+    //# `BurnDown` messages **MUST NOT** be encrypted.
+    #[test]
+    fn refuse_burn_down() {
+        let key = || SymmetricKey::init(|v| v.extend_from_slice(&[0; 32]));
+        let action = Action::BurnDown {
+            message: Timestamped::now(BurnDown::<CipherText> {
+                actor: Encrypted::from_ciphertext(vec![1]),
+                operator: Encrypted::from_ciphertext(vec![2]),
+            }),
+            otp: None,
+            symmetric_keys: BurnDown {
+                actor: key(),
+                operator: key(),
+            },
+        };
+        let msg = ProtocolMessage::new(action, MerkleRoot::new([4; 32]));
+        assert!(matches!(
+            msg.encrypt(&KEY, None),
+            Err(MessageEncryptionError::BurnDown)
+        ));
+
+        // Bypass the check on the sending side
+        let sk = HpkeSecretKey::from_bytes(&[1; 32]);
+        let json = msg.to_json().unwrap();
+        let enc = EncryptedMessage::seal(json.as_bytes(), &sk.public_key()).unwrap();
+        assert!(matches!(
+            enc.decrypt(&sk),
+            Err(MessageDecryptionError::BurnDown)
+        ));
+    }
+}
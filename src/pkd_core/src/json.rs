@@ -0,0 +1,217 @@
+//! Canonical JSON for signatures and hashes
+//!
+//! Signatures and hashes in PKD are calculated over JSON with object keys sorted in ASCII byte order and no duplicate
+//! keys. [`serde_json`] orders keys by struct declaration order (and `#[serde(flatten)]` makes that unpredictable), so
+//! every signature and hash path goes through [`to_canonical_string`] instead.
+
+use std::collections::BTreeSet;
+
+use serde::de::{DeserializeOwned, Error as _};
+use serde_json::Value;
+
+/// Serialize `value` as canonical JSON.
+///
+/// Object keys are sorted in ASCII byte order and there is no whitespace.
+/// Fails if serializing `value` produces an object with duplicate keys.
+pub fn to_canonical_string<T: serde::Serialize + ?Sized>(
+    value: &T,
+) -> Result<String, serde_json::Error> {
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#protocol-signatures
+    //# Object keys **MUST** be sorted in ASCII byte order, and there **MUST** be no duplicate keys.
+    // Round trip through text so duplicate keys produced by `#[serde(flatten)]` are caught
+    let json = serde_json::to_string(value)?;
+    let value = parse_strict(&json)?;
+    let mut out = String::with_capacity(json.len());
+    write_canonical(&value, &mut out);
+    Ok(out)
+}
+
+/// Deserialize `T` from JSON, rejecting objects with duplicate keys at any level.
+pub fn from_str<T: DeserializeOwned>(json: &str) -> Result<T, serde_json::Error> {
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#protocol-messages
+    //# Dictionary keys **MUST** be unique within the same level.
+    T::deserialize(parse_strict(json)?)
+}
+
+/// Parse `json` into a [`Value`], rejecting duplicate keys.
+fn parse_strict(json: &str) -> Result<Value, serde_json::Error> {
+    serde_json::from_str::<StrictValue>(json).map(|v| v.0)
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut entries = map.iter().collect::<Vec<_>>();
+            // Don't rely on `serde_json::Map`'s ordering, `preserve_order` may be enabled by another crate
+            entries.sort_unstable_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(value, out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+/// A [`Value`] that fails to deserialize if any object has duplicate keys
+struct StrictValue(Value);
+
+impl<'de> serde::Deserialize<'de> for StrictValue {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StrictVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for StrictVisitor {
+            type Value = StrictValue;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("any valid JSON value")
+            }
+
+            fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E> {
+                Ok(StrictValue(Value::Bool(v)))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(StrictValue(v.into()))
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(StrictValue(v.into()))
+            }
+
+            fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
+                Ok(StrictValue(v.into()))
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(StrictValue(Value::String(v.to_string())))
+            }
+
+            fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
+                Ok(StrictValue(Value::String(v)))
+            }
+
+            fn visit_unit<E>(self) -> Result<Self::Value, E> {
+                Ok(StrictValue(Value::Null))
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(StrictValue(item)) = seq.next_element()? {
+                    items.push(item);
+                }
+                Ok(StrictValue(Value::Array(items)))
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut seen = BTreeSet::new();
+                let mut object = serde_json::Map::new();
+                while let Some(key) = map.next_key::<String>()? {
+                    if !seen.insert(key.clone()) {
+                        return Err(A::Error::custom(format!("duplicate key '{key}'")));
+                    }
+                    let StrictValue(value) = map.next_value()?;
+                    object.insert(key, value);
+                }
+                Ok(StrictValue(Value::Object(object)))
+            }
+        }
+
+        deserializer.deserialize_any(StrictVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_str, to_canonical_string};
+
+    #[derive(serde::Serialize)]
+    struct Inner {
+        zeta: u8,
+        alpha: &'static str,
+    }
+
+    #[derive(serde::Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct Outer {
+        time: &'static str,
+        #[serde(flatten)]
+        inner: Inner,
+        b_list: Vec<Inner>,
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#protocol-signatures
+    //= type=test
+    //# Object keys **MUST** be sorted in ASCII byte order, and there **MUST** be no duplicate keys.
+    #[test]
+    fn sorted() {
+        let value = Outer {
+            time: "1",
+            inner: Inner {
+                zeta: 2,
+                alpha: "\"quoted\"",
+            },
+            b_list: vec![Inner {
+                zeta: 3,
+                alpha: "é",
+            }],
+        };
+        assert_eq!(
+            to_canonical_string(&value).unwrap(),
+            r#"{"alpha":"\"quoted\"","b-list":[{"alpha":"é","zeta":3}],"time":"1","zeta":2}"#
+        );
+        // ASCII byte order puts uppercase and `!` before lowercase
+        assert_eq!(
+            to_canonical_string(&serde_json::json!({"a": 1, "B": 2, "!c": 3})).unwrap(),
+            r#"{"!c":3,"B":2,"a":1}"#
+        );
+    }
+
+    #[test]
+    fn reject_duplicate_serialized() {
+        #[derive(serde::Serialize)]
+        struct Dup {
+            time: u8,
+            #[serde(flatten)]
+            inner: std::collections::BTreeMap<&'static str, u8>,
+        }
+
+        let dup = Dup {
+            time: 1,
+            inner: [("time", 2)].into(),
+        };
+        assert!(to_canonical_string(&dup).is_err());
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#protocol-messages
+    //= type=test
+    //# Dictionary keys **MUST** be unique within the same level.
+    #[test]
+    fn reject_duplicate_parsed() {
+        assert!(from_str::<serde_json::Value>(r#"{"a":1,"b":{"a":2}}"#).is_ok());
+        assert!(from_str::<serde_json::Value>(r#"{"a":1,"a":2}"#).is_err());
+        assert!(from_str::<serde_json::Value>(r#"[{"b":{"c":1,"c":1}}]"#).is_err());
+    }
+}
@@ -1,9 +1,47 @@
-use crate::utils::{PrefixedBase64, PrefixedBase64Value};
+use std::str::FromStr;
+
+use base64ct::{Base64UrlUnpadded, Encoding};
+use secrecy::ExposeSecret;
+use zeroize::Zeroizing;
+
+use crate::{
+    CryptoError,
+    utils::{PrefixedBase64, PrefixedBase64Error, PrefixedBase64Value},
+};
+
+/// Errors from creating or verifying a [`Signature`]
+#[derive(Debug, thiserror::Error)]
+pub enum SignatureError {
+    /// The public key isn't a valid Ed25519 point
+    #[error("invalid public key")]
+    InvalidPublicKey,
+    /// The public key has low order
+    #[error("public key has low order")]
+    WeakPublicKey,
+    /// The signature doesn't match the message and public key
+    #[error("invalid signature")]
+    Invalid,
+    /// The message has no signature to verify
+    #[error("message is missing a signature")]
+    Missing,
+    /// The message's `key-id` doesn't match any of the actor's public keys
+    #[error("no public key matches the message's key-id")]
+    UnknownKeyId,
+    /// The message has no `recent-merkle-root` to sign
+    #[error("message is missing a recent merkle root")]
+    MissingMerkleRoot,
+    /// The message's action isn't signed
+    #[error("{0} messages are not signed")]
+    UnsignedAction(&'static str),
+    /// The message couldn't be serialized for signing
+    #[error("failed to serialize message: {0}")]
+    Serialize(#[from] serde_json::Error),
+}
 
 /// a [Ed25519](https://en.wikipedia.org/wiki/EdDSA#Ed25519) public key.
 pub type PublicKey = PrefixedBase64<Ed25519Tag>;
 /// A [`PrefixedBase64`] tag for a [Ed25519](https://en.wikipedia.org/wiki/EdDSA#Ed25519) public key.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
 pub struct Ed25519Tag;
 
 impl PrefixedBase64Value for Ed25519Tag {
@@ -13,11 +51,142 @@ impl PrefixedBase64Value for Ed25519Tag {
     const ENCODED_LEN: usize = 43;
 }
 
+impl PublicKey {
+    /// Verify that `signature` is a valid signature of `message` by this key.
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> Result<(), SignatureError> {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#rules-for-cryptography-implementors
+        //# For Ed25519, this means rejecting low-order public keys or non-canonical signatures.
+        let key = ed25519_dalek::VerifyingKey::from_bytes(&self.0)
+            .map_err(|_| SignatureError::InvalidPublicKey)?;
+        if key.is_weak() {
+            return Err(SignatureError::WeakPublicKey);
+        }
+        key.verify_strict(message, &ed25519_dalek::Signature::from_bytes(&signature.0))
+            .map_err(|_| SignatureError::Invalid)
+    }
+}
+
+/// A [Ed25519](https://en.wikipedia.org/wiki/EdDSA#Ed25519) secret key used to sign protocol messages.
+///
+/// The key is zeroized when dropped. Its seed can be exported with [`SigningKey::to_encoded`] and imported with
+/// [`str::parse`], encoded like a [`PublicKey`] but with the [`SigningKey::PREFIX`] prefix.
+pub struct SigningKey(secrecy::SecretBox<[u8; 32]>);
+
+impl SigningKey {
+    /// The prefix of an encoded [`SigningKey`] seed
+    pub const PREFIX: &'static str = "ed25519-sk";
+
+    /// Create a [`SigningKey`] from its 32 byte seed
+    pub fn from_bytes(seed: &[u8; 32]) -> Self {
+        Self(secrecy::SecretBox::new(Box::new(*seed)))
+    }
+
+    /// Generate a fresh [`SigningKey`] using the OS's random number generator
+    pub fn generate() -> Result<Self, CryptoError> {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#rules-for-cryptography-implementors
+        //# For randomness and entropy, the Operating System's Cryptographic Random Number Generator must be used.
+        let mut seed = Zeroizing::new([0; 32]);
+        getrandom::fill(seed.as_mut())?;
+        Ok(Self::from_bytes(&seed))
+    }
+
+    /// Return the [`PublicKey`] for this key
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::new(self.to_dalek().verifying_key().to_bytes())
+    }
+
+    /// Sign `message` with this key
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        use ed25519_dalek::Signer;
+
+        Signature(self.to_dalek().sign(message).to_bytes())
+    }
+
+    /// Export this key's seed, encoded as `ed25519-sk:` followed by unpadded base64url
+    ///
+    /// # Example
+    /// ```
+    /// use pkd_core::SigningKey;
+    /// use secrecy::ExposeSecret;
+    ///
+    /// let key = SigningKey::generate().unwrap();
+    /// let encoded = key.to_encoded();
+    /// assert!(encoded.expose_secret().starts_with("ed25519-sk:"));
+    /// let parsed: SigningKey = encoded.expose_secret().parse().unwrap();
+    /// assert_eq!(parsed.public_key(), key.public_key());
+    /// ```
+    pub fn to_encoded(&self) -> secrecy::SecretString {
+        let encoded = Zeroizing::new(Base64UrlUnpadded::encode_string(self.0.expose_secret()));
+        format!("{}:{}", Self::PREFIX, encoded.as_str()).into()
+    }
+
+    // `ed25519_dalek::SigningKey` zeroizes itself on drop
+    fn to_dalek(&self) -> ed25519_dalek::SigningKey {
+        ed25519_dalek::SigningKey::from_bytes(self.0.expose_secret())
+    }
+}
+
+impl FromStr for SigningKey {
+    type Err = PrefixedBase64Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s
+            .strip_prefix(Self::PREFIX)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .ok_or(PrefixedBase64Error::Prefix(Self::PREFIX))?;
+        if rest.len() != Ed25519Tag::ENCODED_LEN {
+            return Err(PrefixedBase64Error::EncodedLength {
+                expected: Ed25519Tag::ENCODED_LEN,
+                found: rest.len(),
+            });
+        }
+        let mut seed = Zeroizing::new([0; 32]);
+        let wrote = Base64UrlUnpadded::decode(rest, seed.as_mut())
+            .map_err(|_| PrefixedBase64Error::Base64)?
+            .len();
+        if wrote != Ed25519Tag::LEN {
+            return Err(PrefixedBase64Error::Length {
+                expected: Ed25519Tag::LEN,
+                found: wrote,
+            });
+        }
+        Ok(Self::from_bytes(&seed))
+    }
+}
+
+impl std::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SigningKey").field(&"[REDACTED]").finish()
+    }
+}
+
+/// A [Ed25519](https://en.wikipedia.org/wiki/EdDSA#Ed25519) signature encoded in unpadded [`base64url`](https://datatracker.ietf.org/doc/html/rfc4648#section-5)
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct Signature(pub [u8; 64]);
+
+impl serde::Serialize for Signature {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::utils::serde_base64::serialize(&self.0, serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Signature {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = crate::utils::serde_base64::deserialize(deserializer)?;
+        bytes.try_into().map(Self).map_err(|bytes: Vec<u8>| {
+            <D::Error as serde::de::Error>::invalid_length(bytes.len(), &"a 64 byte signature")
+        })
+    }
+}
+
 //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#public-key-encoding
 //= type=test
 #[cfg(test)]
 mod tests {
-    use super::PublicKey;
+    use secrecy::ExposeSecret;
+
+    use super::{PublicKey, Signature, SignatureError, SigningKey};
+    use crate::PrefixedBase64Error;
 
     const KEY: PublicKey = PublicKey::new([
         0x4e, 0x6d, 0x97, 0x06, 0xf6, 0xf4, 0x98, 0x06, 0xf8, 0x95, 0xd5, 0x6e, 0x6c, 0x2c, 0xef,
@@ -41,4 +210,81 @@ mod tests {
         assert!(serde_json::from_str::<PublicKey>("invalid:key").is_err()); // invalid tag
         assert!(serde_json::from_str::<PublicKey>("ed25519:key").is_err()); // invalid encoded key size
     }
+
+    #[test]
+    fn sign_verify() {
+        let sk = SigningKey::from_bytes(&[7; 32]);
+        let pk = sk.public_key();
+        let sig = sk.sign(b"hello");
+        assert!(pk.verify(b"hello", &sig).is_ok());
+        assert!(matches!(
+            pk.verify(b"hellp", &sig),
+            Err(SignatureError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn generate() {
+        let a = SigningKey::generate().unwrap();
+        let b = SigningKey::generate().unwrap();
+        assert_ne!(a.public_key(), b.public_key());
+        let sig = a.sign(b"hello");
+        assert!(a.public_key().verify(b"hello", &sig).is_ok());
+        assert_eq!(format!("{a:?}"), "SigningKey(\"[REDACTED]\")");
+    }
+
+    #[test]
+    fn encode_decode_seed() {
+        let sk = SigningKey::from_bytes(&[7; 32]);
+        let encoded = sk.to_encoded();
+        assert_eq!(
+            encoded.expose_secret(),
+            "ed25519-sk:BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwc"
+        );
+        let parsed: SigningKey = encoded.expose_secret().parse().unwrap();
+        assert_eq!(parsed.public_key(), sk.public_key());
+
+        assert!(matches!(
+            "ed25519:BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwc".parse::<SigningKey>(),
+            Err(PrefixedBase64Error::Prefix("ed25519-sk"))
+        ));
+        assert!(matches!(
+            "ed25519-sk:BwcH".parse::<SigningKey>(),
+            Err(PrefixedBase64Error::EncodedLength { .. })
+        ));
+        assert!(matches!(
+            "ed25519-sk:BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBw!".parse::<SigningKey>(),
+            Err(PrefixedBase64Error::Base64)
+        ));
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#rules-for-cryptography-implementors
+    //= type=test
+    //# For Ed25519, this means rejecting low-order public keys or non-canonical signatures.
+    #[test]
+    fn verify_strict() {
+        // The identity point has order 1
+        let mut identity = [0; 32];
+        identity[0] = 1;
+        assert!(matches!(
+            PublicKey::new(identity).verify(b"hello", &Signature([0; 64])),
+            Err(SignatureError::WeakPublicKey)
+        ));
+
+        // S + L is a non-canonical encoding of the same signature
+        const L: [u8; 32] = [
+            0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9,
+            0xde, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x10,
+        ];
+        let sk = SigningKey::from_bytes(&[7; 32]);
+        let Signature(mut sig) = sk.sign(b"hello");
+        let mut carry = 0;
+        for (s, l) in sig[32..].iter_mut().zip(L) {
+            let sum = *s as u16 + l as u16 + carry;
+            *s = sum as u8;
+            carry = sum >> 8;
+        }
+        assert!(sk.public_key().verify(b"hello", &Signature(sig)).is_err());
+    }
 }
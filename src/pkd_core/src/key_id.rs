@@ -0,0 +1,176 @@
+//! [Key Identifiers](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#key-identifiers)
+
+use std::{fmt::Display, str::FromStr};
+
+use base64ct::{Base64UrlUnpadded, Encoding};
+
+use crate::{CryptoError, PublicKey, SignatureError};
+
+/// A `key-id` hint the directory assigned to a public key, encoded in unpadded [`base64url`](https://datatracker.ietf.org/doc/html/rfc4648#section-5)
+///
+/// Directories generate 256-bit random values, but the spec treats them as arbitrary, so any length is accepted.
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, serde::Serialize, serde::Deserialize,
+)]
+#[serde(transparent)]
+pub struct KeyId(#[serde(with = "crate::utils::serde_base64")] Vec<u8>);
+
+impl KeyId {
+    /// Length of a freshly generated [`KeyId`]
+    pub const LEN: usize = 32;
+
+    /// Create a [`KeyId`] from its raw bytes
+    pub const fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    /// Generate a fresh 256-bit [`KeyId`] using the OS's random number generator
+    pub fn generate() -> Result<Self, CryptoError> {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#key-identifiers
+        //# Every time an `AddKey` message is accepted by the Public Key Directory, the PKD will generate a 256-bit random unique
+        //# `key-id` for that public key.
+        let mut bytes = vec![0; Self::LEN];
+        getrandom::fill(&mut bytes)?;
+        Ok(Self(bytes))
+    }
+
+    /// Return the raw bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Whether this [`KeyId`] is just an encoding of `key`, which makes it unusable as a `key-id`.
+    ///
+    /// # Example
+    /// ```
+    /// use pkd_core::{KeyId, PublicKey};
+    ///
+    /// let key = PublicKey::new([1; 32]);
+    /// assert!(KeyId::from_bytes(vec![1; 32]).is_encoding_of(&key));
+    /// assert!(KeyId::from_bytes(key.to_string().into_bytes()).is_encoding_of(&key));
+    /// assert!(!KeyId::from_bytes(vec![2; 32]).is_encoding_of(&key));
+    /// ```
+    pub fn is_encoding_of(&self, key: &PublicKey) -> bool {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#key-identifiers
+        //# The `key-id` attribute **MUST NOT** be an encoded representation of the public key.
+        self.0 == key.0
+            || self.0 == key.to_string().as_bytes()
+            || self.0 == Base64UrlUnpadded::encode_string(&key.0).as_bytes()
+    }
+}
+
+impl Display for KeyId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&Base64UrlUnpadded::encode_string(&self.0))
+    }
+}
+
+impl FromStr for KeyId {
+    type Err = base64ct::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Base64UrlUnpadded::decode_vec(s).map(Self)
+    }
+}
+
+/// Pick the `key-id` to attach to a message signed by `key`.
+///
+/// `keys` are an actor's public keys along with their `key-id`s, as returned by the directory. Returns [`None`] if
+/// `key` isn't one of them, e.g. for the first `AddKey`, or if its `key-id` is an encoding of the key.
+pub fn select_key_id<'a>(
+    keys: impl IntoIterator<Item = (&'a KeyId, &'a PublicKey)>,
+    key: &PublicKey,
+) -> Option<&'a KeyId> {
+    keys.into_iter()
+        .find(|(id, pk)| *pk == key && !id.is_encoding_of(pk))
+        .map(|(id, _)| id)
+}
+
+/// The public keys to try, in order, when verifying a message with the `key-id` hint `key_id`.
+///
+/// `keys` are an actor's trusted public keys along with their `key-id`s. If `key_id` is given, only its key is
+/// returned, failing if there's no such key. Otherwise every key is returned.
+pub fn candidate_keys<'a>(
+    keys: impl IntoIterator<Item = (&'a KeyId, &'a PublicKey)>,
+    key_id: Option<&KeyId>,
+) -> Result<Vec<&'a PublicKey>, SignatureError> {
+    match key_id {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#addkey-validation-steps
+        //# Otherwise, if the `key-id` is provided, select this public key for the given Actor. If there is no public key for
+        //# this Actor with a matching `key-id`, return an error status.
+        Some(key_id) => keys
+            .into_iter()
+            .find(|(id, pk)| *id == key_id && !id.is_encoding_of(pk))
+            .map(|(_, pk)| vec![pk])
+            .ok_or(SignatureError::UnknownKeyId),
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#addkey-validation-steps
+        //# If a `key-id` was not provided, perform step 6 for each valid and trusted public key for this Actor until one
+        //# succeeds.
+        None => Ok(keys.into_iter().map(|(_, pk)| pk).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyId, candidate_keys, select_key_id};
+    use crate::{PublicKey, SignatureError};
+
+    fn keys() -> Vec<(KeyId, PublicKey)> {
+        vec![
+            (KeyId::from_bytes(vec![1; 32]), PublicKey::new([11; 32])),
+            (KeyId::from_bytes(vec![2; 32]), PublicKey::new([12; 32])),
+            // A misbehaving directory reusing the public key as its id
+            (KeyId::from_bytes(vec![13; 32]), PublicKey::new([13; 32])),
+        ]
+    }
+
+    #[test]
+    fn serde() {
+        let id: KeyId = serde_json::from_str("\"a2V5\"").unwrap();
+        assert_eq!(id.as_bytes(), b"key");
+        assert_eq!(id.to_string(), "a2V5");
+        assert_eq!(serde_json::to_string(&id).unwrap(), "\"a2V5\"");
+        assert!(serde_json::from_str::<KeyId>("\"a2V5!\"").is_err());
+
+        let id = KeyId::generate().unwrap();
+        assert_eq!(id.as_bytes().len(), KeyId::LEN);
+        assert_ne!(id, KeyId::generate().unwrap());
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#key-identifiers
+    //= type=test
+    //# The `key-id` attribute **MUST NOT** be an encoded representation of the public key.
+    #[test]
+    fn select() {
+        let keys = keys();
+        let iter = || keys.iter().map(|(id, pk)| (id, pk));
+        assert_eq!(
+            select_key_id(iter(), &PublicKey::new([12; 32])),
+            Some(&keys[1].0)
+        );
+        assert_eq!(select_key_id(iter(), &PublicKey::new([13; 32])), None);
+        assert_eq!(select_key_id(iter(), &PublicKey::new([14; 32])), None);
+    }
+
+    #[test]
+    fn candidates() {
+        let keys = keys();
+        let iter = || keys.iter().map(|(id, pk)| (id, pk));
+        assert_eq!(
+            candidate_keys(iter(), None).unwrap(),
+            keys.iter().map(|(_, pk)| pk).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            candidate_keys(iter(), Some(&keys[1].0)).unwrap(),
+            vec![&keys[1].1]
+        );
+        assert!(matches!(
+            candidate_keys(iter(), Some(&KeyId::from_bytes(vec![3; 32]))),
+            Err(SignatureError::UnknownKeyId)
+        ));
+        assert!(matches!(
+            candidate_keys(iter(), Some(&keys[2].0)),
+            Err(SignatureError::UnknownKeyId)
+        ));
+    }
+}
@@ -7,10 +7,26 @@
 #![deny(unsafe_code)]
 
 pub mod action;
+pub mod api;
+mod crypto;
+mod encryption;
+pub mod json;
 mod key;
+mod key_id;
 mod merkle;
+mod message;
+mod sigsum;
+pub mod state;
 mod utils;
 
+pub use crypto::{AttributeValue, CryptoError};
+pub use encryption::*;
 pub use key::*;
+pub use key_id::*;
 pub use merkle::*;
-pub use utils::PrefixedBase64;
+pub use message::*;
+pub use sigsum::*;
+pub use utils::{
+    Clock, Context, ContextValue, Encrypted, PrefixedBase64, PrefixedBase64Error, SystemClock,
+    Timestamp, TimestampError, Timestamped,
+};
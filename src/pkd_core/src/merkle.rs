@@ -1,3 +1,7 @@
+//! Merkle tree hashing and proofs, compatible with [RFC 6962](https://datatracker.ietf.org/doc/html/rfc6962#section-2.1) and Sigsum
+
+use sha2::{Digest, Sha256};
+
 use crate::utils::{PrefixedBase64, PrefixedBase64Value};
 
 /// A PKD v1 Merkle root
@@ -14,9 +18,282 @@ impl PrefixedBase64Value for MerkleRootTag {
     const ENCODED_LEN: usize = 43;
 }
 
+/// How recent a `recent-merkle-root` is, see [`root_recency`]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub enum RootRecency {
+    /// Within `ceil(log2(N)^2)` messages, and always within the most recent `N/2`, directories must not reject it for
+    /// staleness
+    Preferred,
+    /// Within the most recent `N/2` messages, directories may reject it for staleness
+    Tolerated,
+    /// Older than `N/2` messages, directories must reject it
+    Stale,
+}
+
+/// Classify a `recent-merkle-root` taken when the ledger had `root_size` messages, for a ledger that now has
+/// `ledger_size` accepted messages.
+///
+/// The root of the empty ledger is [`MerkleRoot::genesis`], with a `root_size` of 0.
+///
+/// # Example
+/// ```
+/// use pkd_core::{RootRecency, root_recency};
+///
+/// // log2(1,000,000)^2 rounds up to 398
+/// assert_eq!(root_recency(1_000_000, 1_000_000), Ok(RootRecency::Preferred));
+/// assert_eq!(root_recency(999_602, 1_000_000), Ok(RootRecency::Preferred));
+/// assert_eq!(root_recency(999_601, 1_000_000), Ok(RootRecency::Tolerated));
+/// assert_eq!(root_recency(500_000, 1_000_000), Ok(RootRecency::Tolerated));
+/// assert_eq!(root_recency(499_999, 1_000_000), Ok(RootRecency::Stale));
+/// ```
+pub fn root_recency(root_size: u64, ledger_size: u64) -> Result<RootRecency, MerkleError> {
+    if root_size > ledger_size {
+        return Err(MerkleError::IndexOutOfRange {
+            index: root_size,
+            tree_size: ledger_size,
+        });
+    }
+    let age = ledger_size - root_size;
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#recent-merkle-root-included-in-plaintext-commitments
+    //# To tolerate large transaction volumes in a short window of time, the chosen Merkle root **MUST** be at least in the
+    //# most recent N/2 messages (for N currently accepted Protocol Messages).
+    if age > ledger_size / 2 {
+        return Ok(RootRecency::Stale);
+    }
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#recent-merkle-root-included-in-plaintext-commitments
+    //# If there are N accepted messages in the ledger, the selected Merkle root **SHOULD** be no more than log_2(N)^2
+    //# messages old (rounded up to the nearest whole number).
+    let preferred = (ledger_size as f64).log2().powi(2).ceil() as u64;
+    Ok(if age <= preferred {
+        RootRecency::Preferred
+    } else {
+        RootRecency::Tolerated
+    })
+}
+
+/// Domain separation prefix for hashing a leaf
+const LEAF_PREFIX: u8 = 0x00;
+/// Domain separation prefix for hashing an interior node
+const NODE_PREFIX: u8 = 0x01;
+
+/// Errors from verifying a Merkle tree proof
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum MerkleError {
+    /// The leaf index isn't inside the tree
+    #[error("leaf index {index} is out of range for a tree of size {tree_size}")]
+    IndexOutOfRange {
+        /// The index of the leaf
+        index: u64,
+        /// The size of the tree
+        tree_size: u64,
+    },
+    /// The newer tree is smaller than the older tree
+    #[error("tree size regressed from {old_size} to {new_size}")]
+    SizeRegression {
+        /// The size of the older tree
+        old_size: u64,
+        /// The size of the newer tree
+        new_size: u64,
+    },
+    /// The proof has the wrong number of nodes for the tree
+    #[error("invalid proof length")]
+    ProofLength,
+    /// The root computed from the proof doesn't match the expected root
+    #[error("computed root doesn't match the expected root")]
+    RootMismatch,
+}
+
+/// A SHA-256 hash of a node in the Merkle tree, encoded in unpadded [`base64url`](https://datatracker.ietf.org/doc/html/rfc4648#section-5)
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub struct MerkleHash(pub [u8; 32]);
+
+impl MerkleHash {
+    /// Hash `data` as a leaf, `SHA-256(0x00 || data)`
+    pub fn leaf(data: &[u8]) -> Self {
+        Self(
+            Sha256::new()
+                .chain_update([LEAF_PREFIX])
+                .chain_update(data)
+                .finalize()
+                .into(),
+        )
+    }
+
+    /// Hash two children into their parent, `SHA-256(0x01 || left || right)`
+    pub fn node(left: &Self, right: &Self) -> Self {
+        Self(
+            Sha256::new()
+                .chain_update([NODE_PREFIX])
+                .chain_update(left.0)
+                .chain_update(right.0)
+                .finalize()
+                .into(),
+        )
+    }
+
+    /// The root hash of a tree with the leaf hashes `leaves`
+    ///
+    /// The hash of an empty tree is the SHA-256 hash of the empty string.
+    pub fn tree(leaves: &[Self]) -> Self {
+        match leaves {
+            [] => Self(Sha256::digest([]).into()),
+            [leaf] => *leaf,
+            _ => {
+                // Split at the largest power of two smaller than the length
+                let split = leaves.len().next_power_of_two() / 2;
+                let (left, right) = leaves.split_at(split);
+                Self::node(&Self::tree(left), &Self::tree(right))
+            }
+        }
+    }
+}
+
+impl From<MerkleHash> for MerkleRoot {
+    fn from(hash: MerkleHash) -> Self {
+        Self::new(hash.0)
+    }
+}
+
+impl From<MerkleRoot> for MerkleHash {
+    fn from(root: MerkleRoot) -> Self {
+        Self(root.0)
+    }
+}
+
+impl serde::Serialize for MerkleHash {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::utils::serde_base64::serialize(&self.0, serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for MerkleHash {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = crate::utils::serde_base64::deserialize(deserializer)?;
+        bytes.try_into().map(Self).map_err(|bytes: Vec<u8>| {
+            <D::Error as serde::de::Error>::invalid_length(bytes.len(), &"a 32 byte hash")
+        })
+    }
+}
+
+impl MerkleRoot {
+    /// The `recent-merkle-root` of the first message in a directory
+    pub const fn genesis() -> Self {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#recent-merkle-root-included-in-plaintext-commitments
+        //# For the first message in a PKD, the "recent" Merkle root **MUST** be set to a sequence of 32 `0x00` bytes.
+        Self::new([0; 32])
+    }
+
+    /// Verify that the leaf `leaf` at `index` is included in the tree of size `tree_size` with this root.
+    ///
+    /// `proof` is the audit path from the leaf to the root, as returned in `inclusion-proof`.
+    /// See [RFC 9162](https://datatracker.ietf.org/doc/html/rfc9162#section-2.1.3.2)
+    pub fn verify_inclusion(
+        &self,
+        tree_size: u64,
+        index: u64,
+        leaf: &MerkleHash,
+        proof: &[MerkleHash],
+    ) -> Result<(), MerkleError> {
+        if index >= tree_size {
+            return Err(MerkleError::IndexOutOfRange { index, tree_size });
+        }
+        let (mut fn_, mut sn) = (index, tree_size - 1);
+        let mut r = *leaf;
+        for p in proof {
+            if sn == 0 {
+                return Err(MerkleError::ProofLength);
+            }
+            if fn_ & 1 == 1 || fn_ == sn {
+                r = MerkleHash::node(p, &r);
+                while fn_ & 1 == 0 && fn_ != 0 {
+                    fn_ >>= 1;
+                    sn >>= 1;
+                }
+            } else {
+                r = MerkleHash::node(&r, p);
+            }
+            fn_ >>= 1;
+            sn >>= 1;
+        }
+        if sn != 0 {
+            return Err(MerkleError::ProofLength);
+        }
+        if r.0 != self.0 {
+            return Err(MerkleError::RootMismatch);
+        }
+        Ok(())
+    }
+
+    /// Verify that the tree of size `new_size` with the root `new` is an append-only extension of the tree of size
+    /// `old_size` with this root.
+    ///
+    /// `proof` is the consistency proof between the two trees. The root of the empty tree is [`MerkleRoot::genesis`].
+    /// See [RFC 9162](https://datatracker.ietf.org/doc/html/rfc9162#section-2.1.4.2)
+    pub fn verify_consistency(
+        &self,
+        old_size: u64,
+        new: &MerkleRoot,
+        new_size: u64,
+        proof: &[MerkleHash],
+    ) -> Result<(), MerkleError> {
+        if old_size > new_size {
+            return Err(MerkleError::SizeRegression { old_size, new_size });
+        }
+        if old_size == new_size || old_size == 0 {
+            if !proof.is_empty() {
+                return Err(MerkleError::ProofLength);
+            }
+            // Any tree is an extension of the empty tree
+            if (old_size == 0 && *self != Self::genesis()) || (old_size == new_size && self != new)
+            {
+                return Err(MerkleError::RootMismatch);
+            }
+            return Ok(());
+        }
+
+        let old = MerkleHash::from(*self);
+        // When the old tree is a complete subtree, its root is the first node of the path
+        let (first, rest) = if old_size.is_power_of_two() {
+            (&old, proof)
+        } else {
+            proof.split_first().ok_or(MerkleError::ProofLength)?
+        };
+        let (mut fn_, mut sn) = (old_size - 1, new_size - 1);
+        while fn_ & 1 == 1 {
+            fn_ >>= 1;
+            sn >>= 1;
+        }
+        let (mut fr, mut sr) = (*first, *first);
+        for c in rest {
+            if sn == 0 {
+                return Err(MerkleError::ProofLength);
+            }
+            if fn_ & 1 == 1 || fn_ == sn {
+                fr = MerkleHash::node(c, &fr);
+                sr = MerkleHash::node(c, &sr);
+                while fn_ & 1 == 0 && fn_ != 0 {
+                    fn_ >>= 1;
+                    sn >>= 1;
+                }
+            } else {
+                sr = MerkleHash::node(&sr, c);
+            }
+            fn_ >>= 1;
+            sn >>= 1;
+        }
+        if sn != 0 {
+            return Err(MerkleError::ProofLength);
+        }
+        if fr != old || sr.0 != new.0 {
+            return Err(MerkleError::RootMismatch);
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::MerkleRoot;
+    use super::{MerkleError, MerkleHash, MerkleRoot, RootRecency, root_recency};
 
     const KEY: MerkleRoot = MerkleRoot::new([
         237, 60, 10, 1, 185, 34, 40, 32, 144, 184, 42, 67, 5, 93, 134, 110, 73, 36, 32, 55, 204,
@@ -24,6 +301,53 @@ mod tests {
     ]);
     const KEY_ENCODED: &str = "pkd-mr-v1:7TwKAbkiKCCQuCpDBV2GbkkkIDfMg2AmG7TMHqXBDJU";
 
+    fn leaves(n: u8) -> Vec<MerkleHash> {
+        (0..n).map(|i| MerkleHash::leaf(&[i])).collect()
+    }
+
+    /// `PATH(m, D[n])` from RFC 6962
+    fn path(m: usize, leaves: &[MerkleHash]) -> Vec<MerkleHash> {
+        if leaves.len() <= 1 {
+            return vec![];
+        }
+        let k = leaves.len().next_power_of_two() / 2;
+        let (left, right) = leaves.split_at(k);
+        if m < k {
+            let mut p = path(m, left);
+            p.push(MerkleHash::tree(right));
+            p
+        } else {
+            let mut p = path(m - k, right);
+            p.push(MerkleHash::tree(left));
+            p
+        }
+    }
+
+    /// `PROOF(m, D[n])` from RFC 6962
+    fn consistency(m: usize, leaves: &[MerkleHash]) -> Vec<MerkleHash> {
+        fn subproof(m: usize, leaves: &[MerkleHash], complete: bool) -> Vec<MerkleHash> {
+            if m == leaves.len() {
+                return if complete {
+                    vec![]
+                } else {
+                    vec![MerkleHash::tree(leaves)]
+                };
+            }
+            let k = leaves.len().next_power_of_two() / 2;
+            let (left, right) = leaves.split_at(k);
+            if m <= k {
+                let mut p = subproof(m, left, complete);
+                p.push(MerkleHash::tree(right));
+                p
+            } else {
+                let mut p = subproof(m - k, right, false);
+                p.push(MerkleHash::tree(left));
+                p
+            }
+        }
+        subproof(m, leaves, true)
+    }
+
     #[test]
     fn encode() {
         assert_eq!(format!("{KEY}"), KEY_ENCODED)
@@ -39,4 +363,193 @@ mod tests {
         assert!(serde_json::from_str::<MerkleRoot>("invalid:key").is_err()); // invalid tag
         assert!(serde_json::from_str::<MerkleRoot>("ed25519:key").is_err()); // invalid encoded key size
     }
+
+    #[test]
+    fn hash_vectors() {
+        // From the RFC 6962 reference implementation
+        assert_eq!(
+            MerkleHash::tree(&[]),
+            MerkleHash(*b"\xe3\xb0\xc4\x42\x98\xfc\x1c\x14\x9a\xfb\xf4\xc8\x99\x6f\xb9\x24\x27\xae\x41\xe4\x64\x9b\x93\x4c\xa4\x95\x99\x1b\x78\x52\xb8\x55")
+        );
+        assert_eq!(
+            MerkleHash::leaf(b""),
+            MerkleHash(*b"\x6e\x34\x0b\x9c\xff\xb3\x7a\x98\x9c\xa5\x44\xe6\xbb\x78\x0a\x2c\x78\x90\x1d\x3f\xb3\x37\x38\x76\x85\x11\xa3\x06\x17\xaf\xa0\x1d")
+        );
+        let leaves = leaves(3);
+        assert_eq!(
+            MerkleHash::tree(&leaves),
+            MerkleHash::node(&MerkleHash::node(&leaves[0], &leaves[1]), &leaves[2])
+        );
+        assert_eq!(
+            serde_json::to_string(&MerkleHash([0; 32])).unwrap(),
+            "\"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\""
+        );
+        assert!(serde_json::from_str::<MerkleHash>("\"AAAA\"").is_err());
+    }
+
+    #[test]
+    fn inclusion() {
+        for size in 1..=17u8 {
+            let leaves = leaves(size);
+            let root = MerkleRoot::from(MerkleHash::tree(&leaves));
+            for (i, leaf) in leaves.iter().enumerate() {
+                let proof = path(i, &leaves);
+                root.verify_inclusion(size.into(), i as u64, leaf, &proof)
+                    .unwrap();
+
+                // Wrong leaf, index or root
+                let other = MerkleHash::leaf(b"other");
+                assert_eq!(
+                    root.verify_inclusion(size.into(), i as u64, &other, &proof),
+                    Err(MerkleError::RootMismatch)
+                );
+                if size > 1 {
+                    let j = (i + 1) % leaves.len();
+                    assert!(
+                        root.verify_inclusion(size.into(), j as u64, leaf, &proof)
+                            .is_err()
+                    );
+                }
+                assert_eq!(
+                    MerkleRoot::default().verify_inclusion(size.into(), i as u64, leaf, &proof),
+                    Err(MerkleError::RootMismatch)
+                );
+
+                // Truncated or extended proofs
+                if let Some((_, short)) = proof.split_last() {
+                    assert!(
+                        root.verify_inclusion(size.into(), i as u64, leaf, short)
+                            .is_err()
+                    );
+                }
+                let mut long = proof.clone();
+                long.push(MerkleHash::default());
+                assert!(
+                    root.verify_inclusion(size.into(), i as u64, leaf, &long)
+                        .is_err()
+                );
+            }
+            assert_eq!(
+                root.verify_inclusion(size.into(), size.into(), &leaves[0], &[]),
+                Err(MerkleError::IndexOutOfRange {
+                    index: size.into(),
+                    tree_size: size.into()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn consistency_proofs() {
+        let leaves = leaves(17);
+        let root = |n: usize| match n {
+            0 => MerkleRoot::genesis(),
+            n => MerkleRoot::from(MerkleHash::tree(&leaves[..n])),
+        };
+        for new_size in 0..=leaves.len() {
+            for old_size in 0..=new_size {
+                let proof = consistency(old_size, &leaves[..new_size]);
+                let proof = if old_size == 0 { vec![] } else { proof };
+                let (old, new) = (root(old_size), root(new_size));
+                let (m, n) = (old_size as u64, new_size as u64);
+                old.verify_consistency(m, &new, n, &proof).unwrap();
+
+                if old_size == 0 {
+                    // Only the genesis root is the root of the empty tree
+                    if new_size > 0 {
+                        assert_eq!(
+                            new.verify_consistency(m, &new, n, &proof),
+                            Err(MerkleError::RootMismatch)
+                        );
+                    }
+                    continue;
+                }
+                let other = MerkleRoot::new([0xff; 32]);
+                assert_eq!(
+                    other.verify_consistency(m, &new, n, &proof),
+                    Err(MerkleError::RootMismatch)
+                );
+                assert_eq!(
+                    old.verify_consistency(m, &other, n, &proof),
+                    Err(MerkleError::RootMismatch)
+                );
+                let mut long = proof.clone();
+                long.push(MerkleHash::default());
+                assert_eq!(
+                    old.verify_consistency(m, &new, n, &long),
+                    Err(MerkleError::ProofLength)
+                );
+                if let Some((_, short)) = proof.split_last() {
+                    assert!(old.verify_consistency(m, &new, n, short).is_err());
+                }
+                if old_size < new_size {
+                    assert_eq!(
+                        new.verify_consistency(n, &old, m, &proof),
+                        Err(MerkleError::SizeRegression {
+                            old_size: n,
+                            new_size: m
+                        })
+                    );
+                }
+            }
+        }
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#recent-merkle-root-included-in-plaintext-commitments
+    //= type=test
+    //# If there are N accepted messages in the ledger, the selected Merkle root **SHOULD** be no more than log_2(N)^2
+    //# messages old (rounded up to the nearest whole number).
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#recent-merkle-root-included-in-plaintext-commitments
+    //= type=test
+    //# To tolerate large transaction volumes in a short window of time, the chosen Merkle root **MUST** be at least in the
+    //# most recent N/2 messages (for N currently accepted Protocol Messages).
+    #[test]
+    fn recency() {
+        use RootRecency::*;
+
+        let cases = [
+            // (root size, ledger size, recency)
+            (0, 0, Preferred),
+            (1, 1, Preferred),
+            (0, 1, Stale),
+            (0, 2, Stale),
+            (1, 2, Preferred),
+            // Small ledgers are bounded by N/2 before log2(N)^2
+            (0, 4, Stale),
+            (2, 4, Preferred),
+            (0, 17, Stale),
+            (8, 17, Stale),
+            (9, 17, Preferred),
+            (0, 32, Stale),
+            (7, 32, Stale),
+            (16, 32, Preferred),
+            (100, 1000, Stale),
+            (499, 1000, Stale),
+            (500, 1000, Tolerated),
+            (899, 1000, Tolerated),
+            (900, 1000, Preferred),
+            (1000, 1000, Preferred),
+            (u64::MAX, u64::MAX, Preferred),
+            (u64::MAX / 2, u64::MAX, Stale),
+        ];
+        for (root, ledger, recency) in cases {
+            assert_eq!(
+                root_recency(root, ledger),
+                Ok(recency),
+                "{root} of {ledger}"
+            );
+        }
+        assert_eq!(
+            root_recency(2, 1),
+            Err(MerkleError::IndexOutOfRange {
+                index: 2,
+                tree_size: 1
+            })
+        );
+        assert_eq!(MerkleRoot::genesis().0, [0; 32]);
+        assert_eq!(
+            MerkleRoot::genesis().to_string(),
+            "pkd-mr-v1:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA"
+        );
+    }
 }
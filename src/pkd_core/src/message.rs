@@ -0,0 +1,276 @@
+//! The top-level [Protocol Message](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#protocol-messages) envelope
+
+use crate::{
+    KeyId, MerkleRoot, PublicKey, Signature, SignatureError, SigningKey,
+    action::Action,
+    candidate_keys,
+    crypto::pae,
+    json,
+    utils::{Context, ContextValue},
+};
+
+/// The `!pkd-context` of a v1 [`ProtocolMessage`]
+pub type ProtocolContext = Context<ProtocolContextTag>;
+
+/// A [`Context`] tag for v1 protocol messages
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub struct ProtocolContextTag;
+
+impl ContextValue for ProtocolContextTag {
+    const CONTEXT: &'static str = "https://github.com/fedi-e2ee/public-key-directory/v1";
+}
+
+/// A PKD [Protocol Message](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#protocol-messages)
+///
+/// This wraps an [`Action`] with the top-level attributes shared by every message.
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProtocolMessage {
+    /// Domain separation for the protocol version
+    #[serde(rename = "!pkd-context")]
+    pub context: ProtocolContext,
+    /// The action, along with its `message` and `symmetric-keys`
+    #[serde(flatten)]
+    pub action: Action,
+    /// A hint for which public key signed this message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<KeyId>,
+    /// A recent Merkle root, used for plaintext commitments.
+    ///
+    /// This is absent for [`Action::RevokeKeyThirdParty`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recent_merkle_root: Option<MerkleRoot>,
+    /// The signature over the message.
+    ///
+    /// This is absent for [`Action::RevokeKeyThirdParty`] and for messages that aren't signed yet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+}
+
+impl ProtocolMessage {
+    /// Wrap `action` in an unsigned [`ProtocolMessage`]
+    pub const fn new(action: Action, recent_merkle_root: MerkleRoot) -> Self {
+        Self {
+            context: ProtocolContext::new(),
+            action,
+            key_id: None,
+            recent_merkle_root: Some(recent_merkle_root),
+            signature: None,
+        }
+    }
+
+    /// Parse a [`ProtocolMessage`] from JSON, rejecting duplicate keys.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        json::from_str(json)
+    }
+
+    /// Serialize this message as canonical JSON, with sorted keys.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#protocol-messages
+        //# Dictionary keys **SHOULD** be sorted.
+        json::to_canonical_string(self)
+    }
+
+    /// The value of the top-level `action` attribute
+    pub const fn action_name(&self) -> &'static str {
+        self.action.name()
+    }
+
+    /// Build the payload covered by this message's signature.
+    ///
+    /// See [Protocol Signatures](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#protocol-signatures)
+    fn signature_payload(&self) -> Result<Vec<u8>, SignatureError> {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekeythirdparty
+        //# Because the contents of this revocation token are signed, no `signature` is needed outside the `message` map.
+        if let Action::RevokeKeyThirdParty { .. } = self.action {
+            return Err(SignatureError::UnsignedAction(self.action_name()));
+        }
+        let root = self
+            .recent_merkle_root
+            .as_ref()
+            .ok_or(SignatureError::MissingMerkleRoot)?
+            .to_string();
+
+        let mut value = serde_json::to_value(&self.action)?;
+        let message = value
+            .get_mut("message")
+            .map(serde_json::Value::take)
+            .unwrap_or_default();
+        let message = json::to_canonical_string(&message)?;
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#protocol-signatures
+        //# To ensure domain separation, we will use [PASETO's PAE()](https://github.com/paseto-standard/paseto-spec/blob/master/docs/01-Protocol-Versions/Common.md#pae-definition)
+        //# function, with a tweak: We will insert the top-level key (`!pkd-context`, `action`, `message`, `recent-merkle-root`)
+        //# before each piece.
+        Ok(pae(&[
+            b"!pkd-context",
+            self.context.as_str().as_bytes(),
+            b"action",
+            self.action_name().as_bytes(),
+            b"message",
+            message.as_bytes(),
+            b"recent-merkle-root",
+            root.as_bytes(),
+        ]))
+    }
+
+    /// Sign this message with `key`, replacing any existing signature.
+    pub fn sign(&mut self, key: &SigningKey) -> Result<(), SignatureError> {
+        let payload = self.signature_payload()?;
+        self.signature = Some(key.sign(&payload));
+        Ok(())
+    }
+
+    /// Verify this message's signature against `key`.
+    pub fn verify(&self, key: &PublicKey) -> Result<(), SignatureError> {
+        let signature = self.signature.as_ref().ok_or(SignatureError::Missing)?;
+        key.verify(&self.signature_payload()?, signature)
+    }
+
+    /// Verify this message's signature against one of an actor's `keys`, returning the key that signed it.
+    ///
+    /// If this message has a `key-id`, only the matching key is tried. Otherwise each key is tried in order.
+    pub fn verify_with_keys<'a>(
+        &self,
+        keys: impl IntoIterator<Item = (&'a KeyId, &'a PublicKey)>,
+    ) -> Result<&'a PublicKey, SignatureError> {
+        let signature = self.signature.as_ref().ok_or(SignatureError::Missing)?;
+        let payload = self.signature_payload()?;
+        candidate_keys(keys, self.key_id.as_ref())?
+            .into_iter()
+            .find(|key| key.verify(&payload, signature).is_ok())
+            .ok_or(SignatureError::Invalid)
+    }
+}
+
+/// An unsigned `Checkpoint` message, for tests
+#[cfg(test)]
+pub(crate) fn checkpoint() -> ProtocolMessage {
+    use crate::{Timestamp, Timestamped, action::Checkpoint};
+
+    let message = Timestamped::new(
+        Timestamp::now(),
+        Checkpoint {
+            from_directory: "https://pkd.example.com".to_string(),
+            from_root: MerkleRoot::new([2; 32]),
+            from_public_key: SigningKey::from_bytes(&[1; 32]).public_key(),
+            to_directory: "https://pkd.example.org".to_string(),
+            to_validated_root: MerkleRoot::new([3; 32]),
+        },
+    );
+    ProtocolMessage::new(Action::Checkpoint { message }, MerkleRoot::new([4; 32]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProtocolMessage, checkpoint};
+    use crate::{KeyId, MerkleRoot, SignatureError, SigningKey, action::Action};
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#protocol-messages
+    //= type=test
+    //# Most protocol messages will consist of the same structure.
+    #[test]
+    fn roundtrip() {
+        let json = r#"{"!pkd-context":"https://github.com/fedi-e2ee/public-key-directory/v1","action":"AddKey","message":{"time":"1724364843","actor":"AQID","public-key":"BAUG"},"symmetric-keys":{"actor":"AAAA","public-key":"AQEB"},"key-id":"a2V5","recent-merkle-root":"pkd-mr-v1:7TwKAbkiKCCQuCpDBV2GbkkkIDfMg2AmG7TMHqXBDJU","signature":"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA"}"#;
+        let msg: ProtocolMessage = serde_json::from_str(json).unwrap();
+        assert!(matches!(msg.action, Action::AddKey(_)));
+        assert_eq!(msg.action_name(), "AddKey");
+        assert_eq!(msg.key_id.as_ref().unwrap().as_bytes(), b"key");
+        assert_eq!(msg.signature.unwrap().0, [0; 64]);
+        assert_eq!(serde_json::to_string(&msg).unwrap(), json);
+    }
+
+    #[test]
+    fn reject_duplicate_keys() {
+        let json = r#"{"!pkd-context":"https://github.com/fedi-e2ee/public-key-directory/v1","action":"RevokeKeyThirdParty","revocation-token":"dG9rZW4","revocation-token":"dG9rZW5z"}"#;
+        assert!(ProtocolMessage::from_json(json).is_err());
+    }
+
+    #[test]
+    fn revoke_third_party() {
+        let json = r#"{"!pkd-context":"https://github.com/fedi-e2ee/public-key-directory/v1","action":"RevokeKeyThirdParty","revocation-token":"dG9rZW4"}"#;
+        let msg: ProtocolMessage = serde_json::from_str(json).unwrap();
+        assert!(msg.recent_merkle_root.is_none() && msg.signature.is_none());
+        assert_eq!(serde_json::to_string(&msg).unwrap(), json);
+    }
+
+    #[test]
+    fn sign_verify() {
+        let sk = SigningKey::from_bytes(&[1; 32]);
+        let other = SigningKey::from_bytes(&[9; 32]);
+        let mut msg = checkpoint();
+        assert!(matches!(
+            msg.verify(&sk.public_key()),
+            Err(SignatureError::Missing)
+        ));
+
+        msg.sign(&sk).unwrap();
+        msg.verify(&sk.public_key()).unwrap();
+        assert!(msg.verify(&other.public_key()).is_err());
+
+        // Survives a JSON roundtrip
+        let json = msg.to_json().unwrap();
+        let mut parsed = ProtocolMessage::from_json(&json).unwrap();
+        parsed.verify(&sk.public_key()).unwrap();
+
+        // Covers the message and the merkle root
+        parsed.recent_merkle_root = Some(MerkleRoot::new([5; 32]));
+        assert!(parsed.verify(&sk.public_key()).is_err());
+        let Action::Checkpoint { message } = &mut msg.action else {
+            unreachable!()
+        };
+        message.inner.to_directory.push('/');
+        assert!(msg.verify(&sk.public_key()).is_err());
+    }
+
+    #[test]
+    fn verify_with_keys() {
+        let sk = SigningKey::from_bytes(&[1; 32]);
+        let keys = [
+            (
+                KeyId::from_bytes(vec![1]),
+                SigningKey::from_bytes(&[9; 32]).public_key(),
+            ),
+            (KeyId::from_bytes(vec![2]), sk.public_key()),
+        ];
+        let iter = || keys.iter().map(|(id, pk)| (id, pk));
+        let mut msg = checkpoint();
+        msg.sign(&sk).unwrap();
+
+        // Without a key-id, every key is tried
+        assert_eq!(msg.verify_with_keys(iter()).unwrap(), &keys[1].1);
+        msg.key_id = Some(keys[1].0.clone());
+        assert_eq!(msg.verify_with_keys(iter()).unwrap(), &keys[1].1);
+        msg.key_id = Some(keys[0].0.clone());
+        assert!(matches!(
+            msg.verify_with_keys(iter()),
+            Err(SignatureError::Invalid)
+        ));
+        msg.key_id = Some(KeyId::from_bytes(vec![3]));
+        assert!(matches!(
+            msg.verify_with_keys(iter()),
+            Err(SignatureError::UnknownKeyId)
+        ));
+    }
+
+    #[test]
+    fn revoke_third_party_unsigned() {
+        let json = r#"{"!pkd-context":"https://github.com/fedi-e2ee/public-key-directory/v1","action":"RevokeKeyThirdParty","revocation-token":"dG9rZW4"}"#;
+        let mut msg: ProtocolMessage = serde_json::from_str(json).unwrap();
+        assert!(matches!(
+            msg.sign(&SigningKey::from_bytes(&[1; 32])),
+            Err(SignatureError::UnsignedAction("RevokeKeyThirdParty"))
+        ));
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#handling-protocol-messages
+    //= type=test
+    //# If it is absent or mismatched, discard the message.
+    #[test]
+    fn reject_context() {
+        let json = r#"{"!pkd-context":"https://github.com/fedi-e2ee/public-key-directory/v2","action":"RevokeKeyThirdParty","revocation-token":"dG9rZW4"}"#;
+        assert!(serde_json::from_str::<ProtocolMessage>(json).is_err());
+        let json = r#"{"action":"RevokeKeyThirdParty","revocation-token":"dG9rZW4"}"#;
+        assert!(serde_json::from_str::<ProtocolMessage>(json).is_err());
+    }
+}
@@ -0,0 +1,686 @@
+//! [Sigsum](https://www.sigsum.org) transparency log integration
+//!
+//! See [Sigsum Integration](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#sigsum-integration)
+
+use std::collections::BTreeSet;
+
+use base64ct::{Base64, Encoding};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    MerkleError, MerkleHash, MerkleRoot, ProtocolContext, ProtocolMessage, PublicKey, Signature,
+    SignatureError, SigningKey, Timestamp,
+    action::{Action, RevocationToken},
+    json,
+};
+
+/// Namespace a Sigsum leaf signature is computed over, before the checksum
+const LEAF_NAMESPACE: &[u8] = b"sigsum.org/v1/tree-leaf\0";
+
+/// The subset of a [`ProtocolMessage`] that's committed to Sigsum
+///
+/// [`Action::RevokeKeyThirdParty`] messages have no `message`, `recent-merkle-root` or `signature`, so their
+/// `revocation-token` is committed instead.
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SigsumBundle {
+    /// Domain separation for the protocol version
+    #[serde(rename = "!pkd-context")]
+    pub context: ProtocolContext,
+    /// The value of the `action` attribute
+    pub action: String,
+    /// The `recent-merkle-root` of the message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recent_merkle_root: Option<MerkleRoot>,
+    /// The `message` map, as sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<serde_json::Value>,
+    /// The `revocation-token` of an [`Action::RevokeKeyThirdParty`] message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revocation_token: Option<RevocationToken>,
+    /// The signature over the message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+}
+
+impl SigsumBundle {
+    /// Serialize this bundle as canonical JSON.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        json::to_canonical_string(self)
+    }
+
+    /// The 32-byte message submitted to Sigsum for this bundle, the SHA-256 hash of its canonical JSON
+    pub fn message(&self) -> Result<[u8; 32], serde_json::Error> {
+        Ok(Sha256::digest(self.to_json()?).into())
+    }
+
+    /// The SHA-256 checksum of this bundle's [`message`](Self::message), as stored in its [`SigsumLeaf`]
+    pub fn checksum(&self) -> Result<[u8; 32], serde_json::Error> {
+        Ok(Sha256::digest(self.message()?).into())
+    }
+
+    /// The [`SigsumLeaf`] for this bundle, as submitted by the directory's `key` with `signature`.
+    pub fn leaf(
+        &self,
+        key: &PublicKey,
+        signature: Signature,
+    ) -> Result<SigsumLeaf, serde_json::Error> {
+        Ok(SigsumLeaf::new(&self.message()?, key, signature))
+    }
+}
+
+impl ProtocolMessage {
+    /// Build the [`SigsumBundle`] the directory commits to Sigsum for this message.
+    ///
+    /// See [Sigsum Integration](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#sigsum-integration)
+    pub fn sigsum_bundle(&self) -> Result<SigsumBundle, serde_json::Error> {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#sigsum-integration
+        //# Note that `key-id` and `symmetric-keys` are deliberately excluded from being committed to Sigsum.
+        let (message, revocation_token) = match &self.action {
+            Action::RevokeKeyThirdParty { revocation_token } => {
+                (None, Some(revocation_token.clone()))
+            }
+            action => {
+                let mut value = serde_json::to_value(action)?;
+                (value.get_mut("message").map(serde_json::Value::take), None)
+            }
+        };
+        Ok(SigsumBundle {
+            context: self.context,
+            action: self.action_name().to_string(),
+            recent_merkle_root: self.recent_merkle_root,
+            message,
+            revocation_token,
+            signature: self.signature,
+        })
+    }
+}
+
+/// A leaf of a Sigsum log
+///
+/// The Public Key Directory submits the SHA-256 hash of each protocol message's Sigsum bundle as the leaf's 32-byte
+/// message, signed with its own key.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SigsumLeaf {
+    /// SHA-256 hash of the submitted message
+    pub checksum: [u8; 32],
+    /// Signature over the checksum by the submitter
+    pub signature: Signature,
+    /// SHA-256 hash of the submitter's public key
+    pub key_hash: [u8; 32],
+}
+
+impl SigsumLeaf {
+    /// Length of a serialized [`SigsumLeaf`]
+    pub const LEN: usize = 32 + 64 + 32;
+
+    /// Create the leaf for `message`, as submitted by `key` with `signature`.
+    pub fn new(message: &[u8], key: &PublicKey, signature: Signature) -> Self {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#sigsum-integration
+        //# After verifying the signature (3), Sigsum stores the SHA256 hash of (1), the SHA256 hash of (2), and the signature (3)
+        //# as-is.
+        Self {
+            checksum: Sha256::digest(message).into(),
+            signature,
+            key_hash: key_hash(key),
+        }
+    }
+
+    /// Create and sign the leaf for `message` with `key`.
+    pub fn sign(message: &[u8], key: &SigningKey) -> Self {
+        let checksum: [u8; 32] = Sha256::digest(message).into();
+        let signature = key.sign(&Self::signed_data(&checksum));
+        Self {
+            checksum,
+            signature,
+            key_hash: key_hash(&key.public_key()),
+        }
+    }
+
+    /// Verify that this leaf was submitted by `key`.
+    pub fn verify(&self, key: &PublicKey) -> Result<(), SignatureError> {
+        if self.key_hash != key_hash(key) {
+            return Err(SignatureError::Invalid);
+        }
+        key.verify(&Self::signed_data(&self.checksum), &self.signature)
+    }
+
+    /// Serialize this leaf as `checksum || signature || key_hash`
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut out = [0; Self::LEN];
+        out[..32].copy_from_slice(&self.checksum);
+        out[32..96].copy_from_slice(&self.signature.0);
+        out[96..].copy_from_slice(&self.key_hash);
+        out
+    }
+
+    /// The [`MerkleHash`] of this leaf in the log
+    pub fn hash(&self) -> MerkleHash {
+        MerkleHash::leaf(&self.to_bytes())
+    }
+
+    /// Verify that this leaf is at `index` in the log of size `tree_size` with the root `root`.
+    pub fn verify_inclusion(
+        &self,
+        root: &MerkleRoot,
+        tree_size: u64,
+        index: u64,
+        proof: &[MerkleHash],
+    ) -> Result<(), MerkleError> {
+        root.verify_inclusion(tree_size, index, &self.hash(), proof)
+    }
+
+    fn signed_data(checksum: &[u8; 32]) -> Vec<u8> {
+        [LEAF_NAMESPACE, checksum].concat()
+    }
+}
+
+/// A Sigsum tree head, the size and root hash of the log at some point in time
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct TreeHead {
+    /// Number of leaves in the log
+    pub size: u64,
+    /// Root hash of the log
+    pub root_hash: MerkleHash,
+}
+
+impl TreeHead {
+    /// The root hash as a [`MerkleRoot`]
+    pub fn root(&self) -> MerkleRoot {
+        self.root_hash.into()
+    }
+
+    /// The [checkpoint](https://github.com/C2SP/C2SP/blob/main/tlog-checkpoint.md) body of this tree head for the log
+    /// with `log_key`, which is what the log and witnesses sign
+    pub fn checkpoint(&self, log_key: &PublicKey) -> String {
+        format!(
+            "sigsum.org/v1/tree/{}\n{}\n{}\n",
+            hex(&key_hash(log_key)),
+            self.size,
+            Base64::encode_string(&self.root_hash.0)
+        )
+    }
+
+    /// Sign this tree head as the log with `key`.
+    pub fn sign(self, key: &SigningKey) -> SignedTreeHead {
+        SignedTreeHead {
+            signature: key.sign(self.checkpoint(&key.public_key()).as_bytes()),
+            tree_head: self,
+        }
+    }
+}
+
+/// A [`TreeHead`] signed by the log
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct SignedTreeHead {
+    /// The tree head
+    pub tree_head: TreeHead,
+    /// The log's signature over the tree head's checkpoint
+    pub signature: Signature,
+}
+
+impl SignedTreeHead {
+    /// Verify that this tree head was signed by the log with `log_key`.
+    pub fn verify(&self, log_key: &PublicKey) -> Result<(), SignatureError> {
+        log_key.verify(
+            self.tree_head.checkpoint(log_key).as_bytes(),
+            &self.signature,
+        )
+    }
+}
+
+/// A witness' cosignature over a [`TreeHead`], using [`cosignature/v1`](https://github.com/C2SP/C2SP/blob/main/tlog-cosignature.md)
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Cosignature {
+    /// SHA-256 hash of the witness' public key
+    pub key_hash: [u8; 32],
+    /// When the witness cosigned the tree head
+    pub timestamp: Timestamp,
+    /// The witness' signature
+    pub signature: Signature,
+}
+
+impl Cosignature {
+    /// Cosign the `tree_head` of the log with `log_key` as the witness with `key` at `timestamp`.
+    pub fn sign(
+        tree_head: &TreeHead,
+        log_key: &PublicKey,
+        key: &SigningKey,
+        timestamp: Timestamp,
+    ) -> Self {
+        Self {
+            key_hash: key_hash(&key.public_key()),
+            timestamp,
+            signature: key.sign(&Self::signed_data(tree_head, log_key, timestamp)),
+        }
+    }
+
+    /// Verify that the witness with `witness_key` cosigned the `tree_head` of the log with `log_key`.
+    pub fn verify(
+        &self,
+        tree_head: &TreeHead,
+        log_key: &PublicKey,
+        witness_key: &PublicKey,
+    ) -> Result<(), SignatureError> {
+        if self.key_hash != key_hash(witness_key) {
+            return Err(SignatureError::Invalid);
+        }
+        witness_key.verify(
+            &Self::signed_data(tree_head, log_key, self.timestamp),
+            &self.signature,
+        )
+    }
+
+    fn signed_data(tree_head: &TreeHead, log_key: &PublicKey, timestamp: Timestamp) -> Vec<u8> {
+        format!(
+            "cosignature/v1\ntime {timestamp}\n{}",
+            tree_head.checkpoint(log_key)
+        )
+        .into_bytes()
+    }
+}
+
+/// A [`SignedTreeHead`] along with the witness cosignatures collected for it
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CosignedTreeHead {
+    /// The signed tree head
+    pub signed_tree_head: SignedTreeHead,
+    /// Cosignatures over the tree head
+    pub cosignatures: Vec<Cosignature>,
+}
+
+/// Errors from verifying a [`CosignedTreeHead`] against a [`WitnessPolicy`]
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum WitnessError {
+    /// The tree head wasn't signed by any of the trusted logs
+    #[error("tree head isn't signed by a trusted log")]
+    UntrustedLog,
+    /// Not enough trusted witnesses cosigned the tree head
+    #[error("tree head is cosigned by {cosigned} trusted witnesses, {quorum} are required")]
+    Quorum {
+        /// The number of distinct trusted witnesses with a valid cosignature
+        cosigned: usize,
+        /// The number of witnesses required
+        quorum: usize,
+    },
+}
+
+/// Which Sigsum logs and witnesses to trust
+///
+/// See [Witness Co-Signing](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#witness-co-signing)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct WitnessPolicy {
+    log_keys: Vec<PublicKey>,
+    witness_keys: Vec<PublicKey>,
+    quorum: usize,
+}
+
+impl WitnessPolicy {
+    /// Trust tree heads signed by any of `log_keys` and cosigned by at least `quorum` of `witness_keys`.
+    ///
+    /// A `quorum` of 0 is raised to 1, since at least one witness has to validate the log.
+    pub fn new(log_keys: Vec<PublicKey>, witness_keys: Vec<PublicKey>, quorum: usize) -> Self {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#witness-co-signing
+        //# At least
+        //# one witness **MUST** also validate that the current state is deterministically reproducible from the history of the
+        //# transparency log.
+        Self {
+            log_keys,
+            witness_keys,
+            quorum: quorum.max(1),
+        }
+    }
+
+    /// The number of distinct witnesses required to cosign a tree head
+    pub fn quorum(&self) -> usize {
+        self.quorum
+    }
+
+    /// Verify that `cth` was signed by a trusted log and cosigned by a quorum of trusted witnesses.
+    ///
+    /// Each witness is counted at most once, and cosignatures from unknown witnesses are ignored.
+    pub fn verify(&self, cth: &CosignedTreeHead) -> Result<WitnessedTreeHead, WitnessError> {
+        let sth = &cth.signed_tree_head;
+        let log_key = self
+            .log_keys
+            .iter()
+            .find(|key| sth.verify(key).is_ok())
+            .ok_or(WitnessError::UntrustedLog)?;
+        let cosigned = self
+            .witness_keys
+            .iter()
+            .filter(|key| {
+                cth.cosignatures
+                    .iter()
+                    .any(|cosig| cosig.verify(&sth.tree_head, log_key, key).is_ok())
+            })
+            .map(|key| key.0)
+            .collect::<BTreeSet<_>>()
+            .len();
+        if cosigned < self.quorum {
+            return Err(WitnessError::Quorum {
+                cosigned,
+                quorum: self.quorum,
+            });
+        }
+        Ok(WitnessedTreeHead(sth.tree_head))
+    }
+}
+
+/// A [`TreeHead`] that's been verified against a [`WitnessPolicy`]
+///
+/// This can only be obtained from [`WitnessPolicy::verify`], so a [`MerkleRoot`] taken from it is known to be witnessed.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct WitnessedTreeHead(TreeHead);
+
+impl WitnessedTreeHead {
+    /// The witnessed tree head
+    pub fn tree_head(&self) -> &TreeHead {
+        &self.0
+    }
+
+    /// Number of leaves in the witnessed log
+    pub fn size(&self) -> u64 {
+        self.0.size
+    }
+
+    /// The witnessed root
+    pub fn root(&self) -> MerkleRoot {
+        self.0.root()
+    }
+
+    /// Verify that `leaf` is at `index` in the witnessed log.
+    pub fn verify_inclusion(
+        &self,
+        leaf: &SigsumLeaf,
+        index: u64,
+        proof: &[MerkleHash],
+    ) -> Result<(), MerkleError> {
+        leaf.verify_inclusion(&self.root(), self.size(), index, proof)
+    }
+
+    /// Verify that the witnessed log is an append-only extension of the log with `old` root and `old_size` leaves.
+    ///
+    /// An empty `old` log has the root [`MerkleRoot::genesis`], see [`MerkleRoot::verify_consistency`].
+    pub fn verify_consistency(
+        &self,
+        old: &MerkleRoot,
+        old_size: u64,
+        proof: &[MerkleHash],
+    ) -> Result<(), MerkleError> {
+        old.verify_consistency(old_size, &self.root(), self.size(), proof)
+    }
+}
+
+/// SHA-256 hash of a public key, which Sigsum uses to identify keys
+fn key_hash(key: &PublicKey) -> [u8; 32] {
+    Sha256::digest(key.0).into()
+}
+
+/// Lowercase hex encoding
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+
+    use super::{Cosignature, CosignedTreeHead, SigsumLeaf, TreeHead, WitnessError, WitnessPolicy};
+    use crate::{
+        MerkleError, MerkleHash, MerkleRoot, ProtocolMessage, SignatureError, SigningKey, Timestamp,
+    };
+
+    fn cosigned(
+        tree_head: TreeHead,
+        log: &SigningKey,
+        witnesses: &[&SigningKey],
+    ) -> CosignedTreeHead {
+        CosignedTreeHead {
+            signed_tree_head: tree_head.sign(log),
+            cosignatures: witnesses
+                .iter()
+                .map(|w| {
+                    Cosignature::sign(
+                        &tree_head,
+                        &log.public_key(),
+                        w,
+                        Timestamp::from_secs(1_700_000_000),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#sigsum-integration
+    //= type=test
+    //# Note that `key-id` and `symmetric-keys` are deliberately excluded from being committed to Sigsum.
+    #[test]
+    fn bundle() {
+        let json = r#"{"!pkd-context":"https://github.com/fedi-e2ee/public-key-directory/v1","action":"AddKey","message":{"time":"1724364843","actor":"AQID","public-key":"BAUG"},"symmetric-keys":{"actor":"AAAA","public-key":"AQEB"},"key-id":"a2V5","recent-merkle-root":"pkd-mr-v1:7TwKAbkiKCCQuCpDBV2GbkkkIDfMg2AmG7TMHqXBDJU","signature":"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA"}"#;
+        let bundle = ProtocolMessage::from_json(json)
+            .unwrap()
+            .sigsum_bundle()
+            .unwrap();
+        let expected = r#"{"!pkd-context":"https://github.com/fedi-e2ee/public-key-directory/v1","action":"AddKey","message":{"actor":"AQID","public-key":"BAUG","time":"1724364843"},"recent-merkle-root":"pkd-mr-v1:7TwKAbkiKCCQuCpDBV2GbkkkIDfMg2AmG7TMHqXBDJU","signature":"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA"}"#;
+        assert_eq!(bundle.to_json().unwrap(), expected);
+
+        // Sigsum's add-leaf takes a 32-byte message and stores its SHA-256 hash as the checksum
+        let message = bundle.message().unwrap();
+        assert_eq!(message, <[u8; 32]>::from(Sha256::digest(expected)));
+        assert_eq!(
+            bundle.checksum().unwrap(),
+            <[u8; 32]>::from(Sha256::digest(message))
+        );
+
+        let sk = SigningKey::from_bytes(&[5; 32]);
+        let leaf = SigsumLeaf::sign(&message, &sk);
+        assert_eq!(leaf.checksum, bundle.checksum().unwrap());
+        assert_eq!(bundle.leaf(&sk.public_key(), leaf.signature).unwrap(), leaf);
+
+        // Computed independently with Python's hashlib and cryptography
+        assert_eq!(
+            super::hex(&leaf.to_bytes()),
+            "cdb73579422f75ace9a63d7d2b0948e2f02277a7237aae542bba9768423834cc\
+             d62a1fc659d402f0d48254a1fd632b8179748e802d9c4e7339533f04e40e6db3\
+             f1021045054524b06f0ef77c9493ce91fd0ac5eb7c69a190bb6e0dd18abc7901\
+             7599776c3085e3f9da0d13071eb0b4ab50fd2bf64c06dd92c2365af3a328eca3"
+        );
+    }
+
+    #[test]
+    fn bundle_revoke_third_party() {
+        let json = r#"{"!pkd-context":"https://github.com/fedi-e2ee/public-key-directory/v1","action":"RevokeKeyThirdParty","revocation-token":"dG9rZW4"}"#;
+        let bundle = ProtocolMessage::from_json(json)
+            .unwrap()
+            .sigsum_bundle()
+            .unwrap();
+        assert_eq!(bundle.to_json().unwrap(), json);
+    }
+
+    #[test]
+    fn sign_verify() {
+        let sk = SigningKey::from_bytes(&[5; 32]);
+        let leaf = SigsumLeaf::sign(b"bundle", &sk);
+        leaf.verify(&sk.public_key()).unwrap();
+        assert_eq!(
+            leaf,
+            SigsumLeaf::new(b"bundle", &sk.public_key(), leaf.signature)
+        );
+        assert!(matches!(
+            leaf.verify(&SigningKey::from_bytes(&[6; 32]).public_key()),
+            Err(SignatureError::Invalid)
+        ));
+
+        let mut forged = leaf.clone();
+        forged.checksum[0] ^= 1;
+        assert!(forged.verify(&sk.public_key()).is_err());
+
+        let bytes = leaf.to_bytes();
+        assert_eq!(&bytes[..32], &leaf.checksum);
+        assert_eq!(&bytes[96..], &leaf.key_hash);
+    }
+
+    #[test]
+    fn inclusion() {
+        let sk = SigningKey::from_bytes(&[5; 32]);
+        let leaves = (0..5u8)
+            .map(|i| SigsumLeaf::sign(&[i], &sk))
+            .collect::<Vec<_>>();
+        let hashes = leaves.iter().map(SigsumLeaf::hash).collect::<Vec<_>>();
+        let root = MerkleRoot::from(MerkleHash::tree(&hashes));
+
+        // The audit path for leaf 4 of 5 is the root of the first 4 leaves
+        let proof = [MerkleHash::tree(&hashes[..4])];
+        leaves[4].verify_inclusion(&root, 5, 4, &proof).unwrap();
+        assert_eq!(
+            leaves[3].verify_inclusion(&root, 5, 4, &proof),
+            Err(MerkleError::RootMismatch)
+        );
+    }
+
+    #[test]
+    fn tree_head() {
+        let log = SigningKey::from_bytes(&[1; 32]);
+        let tree_head = TreeHead {
+            size: 5,
+            root_hash: MerkleHash([0xff; 32]),
+        };
+        let key_hash = super::hex(&Sha256::digest(log.public_key().0));
+        assert_eq!(
+            tree_head.checkpoint(&log.public_key()),
+            format!(
+                "sigsum.org/v1/tree/{key_hash}\n5\n//////////////////////////////////////////8=\n"
+            )
+        );
+
+        let sth = tree_head.sign(&log);
+        sth.verify(&log.public_key()).unwrap();
+        assert!(
+            sth.verify(&SigningKey::from_bytes(&[2; 32]).public_key())
+                .is_err()
+        );
+
+        let witness = SigningKey::from_bytes(&[3; 32]);
+        let cosig = Cosignature::sign(
+            &tree_head,
+            &log.public_key(),
+            &witness,
+            Timestamp::from_secs(1),
+        );
+        cosig
+            .verify(&tree_head, &log.public_key(), &witness.public_key())
+            .unwrap();
+        // Cosignatures are bound to the tree head, the log and the timestamp
+        let other = TreeHead {
+            size: 6,
+            ..tree_head
+        };
+        assert!(
+            cosig
+                .verify(&other, &log.public_key(), &witness.public_key())
+                .is_err()
+        );
+        assert!(
+            cosig
+                .verify(&tree_head, &witness.public_key(), &witness.public_key())
+                .is_err()
+        );
+        let mut forged = cosig;
+        forged.timestamp = Timestamp::from_secs(2);
+        assert!(
+            forged
+                .verify(&tree_head, &log.public_key(), &witness.public_key())
+                .is_err()
+        );
+        assert!(matches!(
+            cosig.verify(&tree_head, &log.public_key(), &log.public_key()),
+            Err(SignatureError::Invalid)
+        ));
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#witness-co-signing
+    //= type=test
+    //# At least
+    //# one witness **MUST** also validate that the current state is deterministically reproducible from the history of the
+    //# transparency log.
+    #[test]
+    fn witness_policy() {
+        let log = SigningKey::from_bytes(&[1; 32]);
+        let [w1, w2, w3] = [2, 3, 4].map(|i| SigningKey::from_bytes(&[i; 32]));
+        let stranger = SigningKey::from_bytes(&[5; 32]);
+        let policy = WitnessPolicy::new(
+            vec![log.public_key()],
+            vec![w1.public_key(), w2.public_key(), w3.public_key()],
+            2,
+        );
+
+        let sk = SigningKey::from_bytes(&[6; 32]);
+        let leaves = (0..3u8)
+            .map(|i| SigsumLeaf::sign(&[i], &sk))
+            .collect::<Vec<_>>();
+        let hashes = leaves.iter().map(SigsumLeaf::hash).collect::<Vec<_>>();
+        let tree_head = TreeHead {
+            size: 3,
+            root_hash: MerkleHash::tree(&hashes),
+        };
+
+        let witnessed = policy
+            .verify(&cosigned(tree_head, &log, &[&w1, &w3]))
+            .unwrap();
+        assert_eq!(
+            witnessed.root(),
+            MerkleRoot::from(MerkleHash::tree(&hashes))
+        );
+        assert_eq!(witnessed.size(), 3);
+        witnessed
+            .verify_inclusion(&leaves[2], 2, &[MerkleHash::tree(&hashes[..2])])
+            .unwrap();
+        witnessed
+            .verify_consistency(&MerkleHash::tree(&hashes[..2]).into(), 2, &[hashes[2]])
+            .unwrap();
+
+        // Repeated and unknown witnesses don't count towards the quorum
+        assert_eq!(
+            policy.verify(&cosigned(tree_head, &log, &[&w1, &w1, &stranger])),
+            Err(WitnessError::Quorum {
+                cosigned: 1,
+                quorum: 2
+            })
+        );
+        // Cosignatures over a different tree head don't count either
+        let mut cth = cosigned(tree_head, &log, &[&w1]);
+        cth.cosignatures.extend(
+            cosigned(
+                TreeHead {
+                    size: 2,
+                    ..tree_head
+                },
+                &log,
+                &[&w2],
+            )
+            .cosignatures,
+        );
+        assert_eq!(
+            policy.verify(&cth),
+            Err(WitnessError::Quorum {
+                cosigned: 1,
+                quorum: 2
+            })
+        );
+        assert_eq!(
+            policy.verify(&cosigned(tree_head, &stranger, &[&w1, &w2, &w3])),
+            Err(WitnessError::UntrustedLog)
+        );
+
+        let policy = WitnessPolicy::new(vec![log.public_key()], vec![w1.public_key()], 0);
+        assert_eq!(policy.quorum(), 1);
+        assert_eq!(
+            policy.verify(&cosigned(tree_head, &log, &[])),
+            Err(WitnessError::Quorum {
+                cosigned: 0,
+                quorum: 1
+            })
+        );
+    }
+}
@@ -0,0 +1,971 @@
+//! Reproduce a Public Key Directory's state by replaying its history
+//!
+//! See [Message Blocks](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#message-blocks)
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    CryptoError, MerkleRoot, ProtocolMessage, PublicKey, SignatureError,
+    action::{Action, ActorId, DecryptedAction, RevocationError},
+};
+
+mod policy;
+
+pub use policy::{ActorStatus, Effect, Recovery, evaluate};
+
+/// A validation rule that a message in the history broke
+#[derive(Debug, thiserror::Error)]
+pub enum Rule {
+    /// The message's attributes couldn't be decrypted
+    #[error("failed to decrypt message: {0}")]
+    Decrypt(#[from] CryptoError),
+    /// The message's signature isn't valid for any of the signer's keys
+    #[error("invalid signature: {0}")]
+    Signature(#[from] SignatureError),
+    /// The message was already accepted earlier in the history
+    #[error("message was replayed")]
+    Replayed,
+    /// There's no prior message for the actor
+    #[error("no prior message for actor {0}")]
+    UnknownActor(ActorId),
+    /// An `AddKey` was self-signed while the actor has keys, or a `RevokeKey` was signed by the key it revokes
+    #[error("message is signed by the key it affects")]
+    SelfSigned,
+    /// The key is already one of the actor's keys
+    #[error("public key is already active for the actor")]
+    DuplicateKey,
+    /// The key was revoked earlier, by the actor or a third party
+    #[error("public key was revoked")]
+    RevokedKey,
+    /// The key isn't one of the actor's keys
+    #[error("public key isn't active for the actor")]
+    UnknownKey,
+    /// A `RevokeKey` would leave the actor without any keys
+    #[error("can't revoke the actor's last public key")]
+    LastKey,
+    /// The actor has no keys to sign the message with
+    #[error("actor has no public keys")]
+    NoKeys,
+    /// The revocation token is invalid
+    #[error("invalid revocation token: {0}")]
+    Revocation(#[from] RevocationError),
+    /// The target of a `MoveIdentity` already has public keys
+    #[error("new actor already has public keys")]
+    NewActorHasKeys,
+    /// A `BurnDown` targets a Fireproof actor
+    #[error("actor is fireproof")]
+    Fireproof,
+    /// A `Fireproof` targets an actor that is already Fireproof
+    #[error("actor is already fireproof")]
+    AlreadyFireproof,
+    /// An `UndoFireproof` targets an actor that isn't Fireproof
+    #[error("actor isn't fireproof")]
+    NotFireproof,
+    /// A `BurnDown` was issued by an operator of a different instance
+    #[error("operator {0} isn't on the actor's instance")]
+    ForeignOperator(ActorId),
+    /// The `aux-type` isn't a supported extension
+    #[error("unsupported aux-type {0}")]
+    UnsupportedAuxType(String),
+    /// The `aux-id` doesn't match the `aux-type` and `aux-data`
+    #[error("aux-id doesn't match the aux-data")]
+    InvalidAuxId,
+    /// A `RevokeAuxData` has neither an `aux-id` nor `aux-data`
+    #[error("either aux-id or aux-data is required")]
+    MissingAuxId,
+    /// The actor already has this auxiliary data
+    #[error("auxiliary data {0} already exists")]
+    DuplicateAuxData(String),
+    /// The actor has no auxiliary data with this `aux-id`
+    #[error("no auxiliary data {0}")]
+    UnknownAuxData(String),
+}
+
+/// A message that the replayed history diverged at
+#[derive(Debug, thiserror::Error)]
+#[error("message {index} ({action}) diverged: {rule}")]
+pub struct Divergence {
+    /// The index of the message in the history, starting from 0
+    pub index: u64,
+    /// The value of the message's `action` attribute
+    pub action: &'static str,
+    /// The rule the message broke
+    pub rule: Rule,
+}
+
+/// An Auxiliary Data record of an actor
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AuxRecord {
+    /// The identifier used by the Auxiliary Data extension
+    pub aux_type: String,
+    /// The auxiliary data
+    pub data: Vec<u8>,
+}
+
+/// The current state of one actor
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct ActorState {
+    /// The actor's valid public keys, in the order they were added
+    pub keys: Vec<PublicKey>,
+    /// Public keys the actor revoked, which can't be added again
+    pub revoked_keys: BTreeSet<PublicKey>,
+    /// The actor's Auxiliary Data, by `aux-id`
+    pub aux_data: BTreeMap<String, AuxRecord>,
+    /// Whether the actor has opted out of `BurnDown`
+    pub fireproof: bool,
+    /// Whether the actor's keys were burned down, allowing a self-signed `AddKey`
+    pub burned_down: bool,
+}
+
+impl ActorState {
+    /// Clear every key and Auxiliary Data, as if by a `BurnDown`
+    fn burn_down(&mut self) {
+        self.keys.clear();
+        self.aux_data.clear();
+        self.burned_down = true;
+    }
+}
+
+/// The state of a Public Key Directory, reproduced by replaying its history in order
+///
+/// Rules that depend on the directory's private state, such as TOTP enrollment, the `Checkpoint` allow-list or message
+/// freshness, can't be replayed and are skipped. `key-id`s are assigned by the directory and aren't part of the history
+/// either, so every valid key of the signer is tried.
+#[derive(Debug, Default, Clone)]
+pub struct State {
+    actors: BTreeMap<ActorId, ActorState>,
+    /// Public keys revoked by a third party, for every actor
+    revoked: BTreeSet<PublicKey>,
+    signatures: BTreeSet<[u8; 64]>,
+    aux_types: Option<BTreeSet<String>>,
+    len: u64,
+}
+
+impl State {
+    /// Create the empty state of a new directory, accepting every `aux-type`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only accept Auxiliary Data of the extensions `aux_types`
+    pub fn with_aux_types(mut self, aux_types: impl IntoIterator<Item = String>) -> Self {
+        self.aux_types = Some(aux_types.into_iter().collect());
+        self
+    }
+
+    /// Replay `history` from the first message, returning the resulting state
+    pub fn replay<'a>(
+        history: impl IntoIterator<Item = &'a ProtocolMessage>,
+    ) -> Result<Self, Divergence> {
+        let mut state = Self::new();
+        for message in history {
+            state.apply(message)?;
+        }
+        Ok(state)
+    }
+
+    /// The number of messages applied so far
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether no messages were applied yet
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The state of `actor`, if any message mentioned it
+    pub fn actor(&self, actor: &ActorId) -> Option<&ActorState> {
+        self.actors.get(actor)
+    }
+
+    /// Every actor any message mentioned, along with their state
+    pub fn actors(&self) -> impl Iterator<Item = (&ActorId, &ActorState)> {
+        self.actors.iter()
+    }
+
+    /// Decrypt and apply the next `message` in the history.
+    ///
+    /// The state is left unchanged if the message is rejected.
+    pub fn apply(&mut self, message: &ProtocolMessage) -> Result<(), Divergence> {
+        let decrypted = match (&message.recent_merkle_root, &message.action) {
+            (Some(root), action) => action.decrypt(root),
+            // Only a third party revocation has no root, and nothing to decrypt
+            (None, action @ Action::RevokeKeyThirdParty { .. }) => {
+                action.decrypt(&MerkleRoot::genesis())
+            }
+            (None, _) => {
+                return Err(self.diverge(message, SignatureError::MissingMerkleRoot.into()));
+            }
+        };
+        match decrypted {
+            Ok(decrypted) => self.apply_decrypted(message, &decrypted),
+            Err(e) => Err(self.diverge(message, e.into())),
+        }
+    }
+
+    /// Apply the next `message` in the history, whose attributes [`State::apply`] decrypted to `decrypted`
+    fn apply_decrypted(
+        &mut self,
+        message: &ProtocolMessage,
+        decrypted: &DecryptedAction,
+    ) -> Result<(), Divergence> {
+        self.validate(message, decrypted)
+            .map_err(|rule| self.diverge(message, rule))?;
+        if let Some(signature) = &message.signature {
+            self.signatures.insert(signature.0);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    fn diverge(&self, message: &ProtocolMessage, rule: Rule) -> Divergence {
+        Divergence {
+            index: self.len,
+            action: message.action_name(),
+            rule,
+        }
+    }
+
+    /// The state of an actor that a prior message mentioned
+    fn known(&self, actor: &ActorId) -> Result<&ActorState, Rule> {
+        self.actors
+            .get(actor)
+            .ok_or_else(|| Rule::UnknownActor(actor.clone()))
+    }
+
+    /// Validate `message` and apply its effects
+    fn validate(
+        &mut self,
+        message: &ProtocolMessage,
+        decrypted: &DecryptedAction,
+    ) -> Result<(), Rule> {
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-blocks
+        //# Public Key Directory servers **MUST** reject any replayed Protocol Message,
+        //# even if it's otherwise valid.
+        if let Some(signature) = &message.signature
+            && self.signatures.contains(&signature.0)
+        {
+            return Err(Rule::Replayed);
+        }
+
+        match decrypted {
+            DecryptedAction::AddKey(add) => {
+                let state = self.actors.get(&add.actor);
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekey
+                //# There is no undo operation for public key revocation.
+                if self.revoked.contains(&add.public_key)
+                    || state.is_some_and(|s| s.revoked_keys.contains(&add.public_key))
+                {
+                    return Err(Rule::RevokedKey);
+                }
+                match state.filter(|s| !s.keys.is_empty()) {
+                    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#addkey-validation-steps
+                    //# If there are no other public keys for the provided Actor, use the given public key (it is self-signed) and go to
+                    //# step 6.
+                    None => message.verify(&add.public_key)?,
+                    Some(state) => {
+                        if state.keys.contains(&add.public_key) {
+                            return Err(Rule::DuplicateKey);
+                        }
+                        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#addkey
+                        //# Self-signing is not permitted for any `AddKey` while a valid public key exists for this user.
+                        if let Err(e) = verify_any(message, &state.keys) {
+                            return Err(match message.verify(&add.public_key) {
+                                Ok(()) => Rule::SelfSigned,
+                                Err(_) => e.into(),
+                            });
+                        }
+                    }
+                }
+                let state = self.actors.entry(add.actor.clone()).or_default();
+                state.keys.push(add.public_key);
+                state.burned_down = false;
+            }
+            DecryptedAction::RevokeKey(revoke) => {
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekey-validation-steps
+                //# If there is no prior Protocol Message with a plaintext Actor ID that matches the decrypted `message.actor`, abort.
+                let state = self.known(&revoke.actor)?;
+                if !state.keys.contains(&revoke.public_key) {
+                    return Err(Rule::UnknownKey);
+                }
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekey
+                //# Attempting to issue a `RevokeKey` **MUST** fail unless there is another public key associated with this Actor.
+                if state.keys.len() == 1 {
+                    return Err(Rule::LastKey);
+                }
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekey
+                //# The key
+                //# used to sign the `RevokeKey` cannot be the same as the key being revoked.
+                let others = state
+                    .keys
+                    .iter()
+                    .filter(|key| **key != revoke.public_key)
+                    .copied()
+                    .collect::<Vec<_>>();
+                if let Err(e) = verify_any(message, &others) {
+                    return Err(match message.verify(&revoke.public_key) {
+                        Ok(()) => Rule::SelfSigned,
+                        Err(_) => e.into(),
+                    });
+                }
+                let state = self.actors.get_mut(&revoke.actor).expect("actor is known");
+                state.keys.retain(|key| *key != revoke.public_key);
+                state.revoked_keys.insert(revoke.public_key);
+            }
+            DecryptedAction::RevokeKeyThirdParty(token) => {
+                let public_key = token.verify()?;
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekeythirdparty-validation-steps
+                //# If the signature is valid in step 3, revoke this public key for all Actors that share it.
+                let mut revoked = false;
+                for state in self.actors.values_mut() {
+                    if !state.keys.contains(&public_key) {
+                        continue;
+                    }
+                    revoked = true;
+                    match evaluate(Recovery::RevokeKeyThirdParty, (&*state).into())? {
+                        Effect::RevokeKey => state.keys.retain(|key| *key != public_key),
+                        _ => state.burn_down(),
+                    }
+                }
+                if !revoked {
+                    return Err(Rule::UnknownKey);
+                }
+                self.revoked.insert(public_key);
+            }
+            DecryptedAction::MoveIdentity(moved) => {
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#moveidentity-validation-steps
+                //# If there is no prior Protocol Message with a plaintext Actor ID that matches the decrypted `message.old-actor`,
+                //# abort.
+                let old = self.known(&moved.old_actor)?;
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#moveidentity
+                //# This message **MUST** be rejected if there are existing public keys for the target `new-actor`.
+                if self
+                    .actors
+                    .get(&moved.new_actor)
+                    .is_some_and(|s| !s.keys.is_empty())
+                {
+                    return Err(Rule::NewActorHasKeys);
+                }
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#moveidentity
+                //# The message **MUST** be signed by a valid secret key for the `old-actor`
+                verify_any(message, &old.keys)?;
+                // Revocations are permanent, so the old actor keeps them
+                let revoked_keys = old.revoked_keys.clone();
+                let mut new = self
+                    .actors
+                    .insert(
+                        moved.old_actor.clone(),
+                        ActorState {
+                            revoked_keys,
+                            ..ActorState::default()
+                        },
+                    )
+                    .expect("actor is known");
+                // And so does the new actor, e.g. after a `BurnDown`
+                if let Some(prior) = self.actors.get(&moved.new_actor) {
+                    new.revoked_keys.extend(&prior.revoked_keys);
+                }
+                self.actors.insert(moved.new_actor.clone(), new);
+            }
+            DecryptedAction::BurnDown(burn) => {
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#burndown-validation-steps
+                //# If there is no prior Protocol Message with a plaintext Actor ID that matches the decrypted `message.actor`, abort.
+                evaluate(Recovery::BurnDown, self.known(&burn.actor)?.into())?;
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#burndown
+                //# Unlike most Fediverse messages, a `BurnDown` is issued by an operator account on the Fediverse instance that hosts the
+                //# Actor in question.
+                if !burn.operator.same_instance(&burn.actor) {
+                    return Err(Rule::ForeignOperator(burn.operator.clone()));
+                }
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#burndown-validation-steps
+                //# If a `key-id` was not provided, perform step 8 for each valid and trusted public key for this Actor (Operator) until
+                //# one succeeds.
+                verify_any(message, &self.known(&burn.operator)?.keys)?;
+                self.actors
+                    .get_mut(&burn.actor)
+                    .expect("actor is known")
+                    .burn_down();
+            }
+            DecryptedAction::Fireproof(fireproof) => {
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#fireproof-validation-steps
+                //# If there is no prior Protocol Message with a plaintext Actor ID that matches the decrypted `message.actor`, abort.
+                let state = self.known(&fireproof.actor)?;
+                evaluate(Recovery::Fireproof, state.into())?;
+                verify_any(message, &state.keys)?;
+                self.actors
+                    .get_mut(&fireproof.actor)
+                    .expect("actor is known")
+                    .fireproof = true;
+            }
+            DecryptedAction::UndoFireproof(undo) => {
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#undofireproof-validation-steps
+                //# If there is no prior Protocol Message with a plaintext Actor ID that matches the decrypted `message.actor`, abort.
+                let state = self.known(&undo.actor)?;
+                evaluate(Recovery::UndoFireproof, state.into())?;
+                verify_any(message, &state.keys)?;
+                self.actors
+                    .get_mut(&undo.actor)
+                    .expect("actor is known")
+                    .fireproof = false;
+            }
+            DecryptedAction::AddAuxData(aux) => {
+                if !aux.is_valid_id(&aux.inner.aux_data) {
+                    return Err(Rule::InvalidAuxId);
+                }
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#addauxdata-validation-steps
+                //# If `message.aux-type` does not match any of the identifiers for supported Auxiliary Data extensions for this Public
+                //# Key Directory, abort.
+                if self
+                    .aux_types
+                    .as_ref()
+                    .is_some_and(|types| !types.contains(&aux.aux_type))
+                {
+                    return Err(Rule::UnsupportedAuxType(aux.aux_type.clone()));
+                }
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#addauxdata-validation-steps
+                //# If there is no prior Protocol Message with a plaintext Actor ID that matches the decrypted `message.actor`, abort.
+                let state = self.known(&aux.inner.actor)?;
+                let aux_id = crate::action::aux_data_id(&aux.aux_type, &aux.inner.aux_data);
+                if state.aux_data.contains_key(&aux_id) {
+                    return Err(Rule::DuplicateAuxData(aux_id));
+                }
+                verify_any(message, &state.keys)?;
+                self.actors
+                    .get_mut(&aux.inner.actor)
+                    .expect("actor is known")
+                    .aux_data
+                    .insert(
+                        aux_id,
+                        AuxRecord {
+                            aux_type: aux.aux_type.clone(),
+                            data: aux.inner.aux_data.clone(),
+                        },
+                    );
+            }
+            DecryptedAction::RevokeAuxData(aux) => {
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokeauxdata-validation-steps
+                //# If there is no prior Protocol Message with a plaintext Actor ID that matches the decrypted `message.actor`, abort.
+                let state = self.known(&aux.inner.actor)?;
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokeauxdata-validation-steps
+                //# Otherwise, if `message.aux-data` is provided, recalculate the expected `aux-id`, then proceed to step 7.
+                let aux_id = match (&aux.aux_id, &aux.inner.aux_data) {
+                    (Some(_), Some(data)) if !aux.is_valid_id(data) => {
+                        return Err(Rule::InvalidAuxId);
+                    }
+                    (Some(aux_id), _) => aux_id.clone(),
+                    (None, Some(data)) => crate::action::aux_data_id(&aux.aux_type, data),
+                    (None, None) => return Err(Rule::MissingAuxId),
+                };
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokeauxdata-validation-steps
+                //# If there is no existing Auxiliary Data with a matching `aux-id` (whether provided or calculated), abort.
+                if state
+                    .aux_data
+                    .get(&aux_id)
+                    .is_none_or(|record| record.aux_type != aux.aux_type)
+                {
+                    return Err(Rule::UnknownAuxData(aux_id));
+                }
+                verify_any(message, &state.keys)?;
+                self.actors
+                    .get_mut(&aux.inner.actor)
+                    .expect("actor is known")
+                    .aux_data
+                    .remove(&aux_id);
+            }
+            DecryptedAction::Checkpoint(checkpoint) => {
+                //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#checkpoint-validation-steps
+                //# Validate the message signature for the given public key in `from-public-key`.
+                message.verify(&checkpoint.from_public_key)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Verify `message` against each of `keys` until one succeeds
+fn verify_any(message: &ProtocolMessage, keys: &[PublicKey]) -> Result<(), SignatureError> {
+    let mut result = Err(SignatureError::Invalid);
+    for key in keys {
+        result = message.verify(key);
+        if result.is_ok() {
+            break;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Rule, State};
+    use crate::{
+        MerkleRoot, ProtocolMessage, SigningKey, Timestamp, Timestamped,
+        action::{
+            Action, ActorId, AddAuxData, AddAuxDataInner, AddOrRevokeKey, AddOrRevokeKeyInner,
+            AuxData, BurnDown, Encrypt, FireproofInner, FireproofOrUndo, MoveIdentity, PlainText,
+            RevocationToken, RevokeAuxData, RevokeAuxDataInner,
+        },
+    };
+
+    const ROOT: MerkleRoot = MerkleRoot::new([7; 32]);
+    const TIME: Timestamp = Timestamp::from_secs(1_700_000_000);
+
+    fn actor(name: &str) -> ActorId {
+        format!("https://example.com/users/{name}").parse().unwrap()
+    }
+
+    fn key(i: u8) -> SigningKey {
+        SigningKey::from_bytes(&[i; 32])
+    }
+
+    fn signed(action: Action, key: &SigningKey) -> ProtocolMessage {
+        let mut message = ProtocolMessage::new(action, ROOT);
+        message.sign(key).unwrap();
+        message
+    }
+
+    fn key_message(actor: &ActorId, key: &SigningKey) -> AddOrRevokeKey {
+        let (cipher, symmetric_keys) = AddOrRevokeKeyInner::<PlainText> {
+            actor: actor.clone(),
+            public_key: key.public_key(),
+        }
+        .encrypt(&ROOT)
+        .unwrap();
+        AddOrRevokeKey {
+            message: Timestamped::new(TIME, cipher),
+            symmetric_keys,
+        }
+    }
+
+    fn add_key(actor: &ActorId, key: &SigningKey) -> Action {
+        Action::AddKey(key_message(actor, key))
+    }
+
+    fn revoke_key(actor: &ActorId, key: &SigningKey) -> Action {
+        Action::RevokeKey(key_message(actor, key))
+    }
+
+    fn fireproof_message(actor: &ActorId) -> FireproofOrUndo {
+        let (cipher, symmetric_keys) = FireproofInner::<PlainText> {
+            actor: actor.clone(),
+        }
+        .encrypt(&ROOT)
+        .unwrap();
+        FireproofOrUndo {
+            message: Timestamped::new(TIME, cipher),
+            symmetric_keys,
+        }
+    }
+
+    fn burn_down(actor: &ActorId, operator: &ActorId) -> Action {
+        let (cipher, symmetric_keys) = BurnDown::<PlainText> {
+            actor: actor.clone(),
+            operator: operator.clone(),
+        }
+        .encrypt(&ROOT)
+        .unwrap();
+        Action::BurnDown {
+            message: Timestamped::new(TIME, cipher),
+            otp: None,
+            symmetric_keys,
+        }
+    }
+
+    fn move_identity(old_actor: &ActorId, new_actor: &ActorId) -> Action {
+        let (cipher, symmetric_keys) = MoveIdentity::<PlainText> {
+            old_actor: old_actor.clone(),
+            new_actor: new_actor.clone(),
+        }
+        .encrypt(&ROOT)
+        .unwrap();
+        Action::MoveIdentity {
+            message: Timestamped::new(TIME, cipher),
+            symmetric_keys,
+        }
+    }
+
+    fn add_aux(actor: &ActorId, data: &[u8]) -> Action {
+        let (cipher, symmetric_keys) = AddAuxDataInner::<PlainText> {
+            actor: actor.clone(),
+            aux_data: data.to_vec(),
+        }
+        .encrypt(&ROOT)
+        .unwrap();
+        Action::AddAuxData(AddAuxData {
+            message: Timestamped::new(TIME, AuxData::new("age-v1".to_string(), data, cipher)),
+            symmetric_keys,
+        })
+    }
+
+    fn revoke_aux(actor: &ActorId, data: Option<&[u8]>, aux_id: Option<String>) -> Action {
+        let (cipher, symmetric_keys) = RevokeAuxDataInner::<PlainText> {
+            actor: actor.clone(),
+            aux_data: data.map(<[u8]>::to_vec),
+        }
+        .encrypt(&ROOT)
+        .unwrap();
+        Action::RevokeAuxData(RevokeAuxData {
+            message: Timestamped::new(
+                TIME,
+                AuxData {
+                    aux_type: "age-v1".to_string(),
+                    aux_id,
+                    inner: cipher,
+                },
+            ),
+            symmetric_keys,
+        })
+    }
+
+    fn revoke_third_party(key: &SigningKey) -> ProtocolMessage {
+        ProtocolMessage {
+            recent_merkle_root: None,
+            ..ProtocolMessage::new(
+                Action::RevokeKeyThirdParty {
+                    revocation_token: RevocationToken::issue(key),
+                },
+                ROOT,
+            )
+        }
+    }
+
+    /// Apply `message`, expecting it to diverge at the current index
+    fn reject(state: &mut State, message: &ProtocolMessage) -> Rule {
+        let len = state.len();
+        let divergence = state.apply(message).unwrap_err();
+        assert_eq!(divergence.index, len);
+        assert_eq!(divergence.action, message.action_name());
+        assert_eq!(state.len(), len);
+        divergence.rule
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-blocks
+    //= type=test
+    //# Any other machine **MUST** be able to reproduce the same state as the Public Key Directory by replaying the entire
+    //# message history from the first block to the current one.
+    #[test]
+    fn replay() {
+        let alice = actor("alice");
+        let (k1, k2) = (key(1), key(2));
+        let history = [
+            signed(add_key(&alice, &k1), &k1),
+            signed(add_key(&alice, &k2), &k1),
+            signed(add_aux(&alice, b"age1alice"), &k2),
+            signed(add_aux(&alice, b"age1other"), &k2),
+            signed(revoke_aux(&alice, Some(b"age1other"), None), &k1),
+            signed(revoke_key(&alice, &k1), &k2),
+            signed(Action::Fireproof(fireproof_message(&alice)), &k2),
+        ];
+        let state = State::replay(&history).unwrap();
+        assert_eq!(state.len(), 7);
+        let alice = state.actor(&alice).unwrap();
+        assert_eq!(alice.keys, vec![k2.public_key()]);
+        assert_eq!(
+            alice.aux_data.keys().collect::<Vec<_>>(),
+            vec![&crate::action::aux_data_id("age-v1", b"age1alice")]
+        );
+        assert!(alice.fireproof && !alice.burned_down);
+        assert_eq!(state.actors().count(), 1);
+    }
+
+    #[test]
+    fn keys() {
+        let alice = actor("alice");
+        let (k1, k2, k3) = (key(1), key(2), key(3));
+        let mut state = State::new();
+        assert!(state.is_empty());
+
+        assert!(matches!(
+            reject(&mut state, &signed(add_key(&alice, &k1), &k2)),
+            Rule::Signature(_)
+        ));
+        let first = signed(add_key(&alice, &k1), &k1);
+        state.apply(&first).unwrap();
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#message-blocks
+        //= type=test
+        //# Public Key Directory servers **MUST** reject any replayed Protocol Message,
+        //# even if it's otherwise valid.
+        assert!(matches!(reject(&mut state, &first), Rule::Replayed));
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#addkey
+        //= type=test
+        //# Self-signing is not permitted for any `AddKey` while a valid public key exists for this user.
+        assert!(matches!(
+            reject(&mut state, &signed(add_key(&alice, &k2), &k2)),
+            Rule::SelfSigned
+        ));
+        assert!(matches!(
+            reject(&mut state, &signed(add_key(&alice, &k1), &k1)),
+            Rule::DuplicateKey
+        ));
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekey
+        //= type=test
+        //# Attempting to issue a `RevokeKey` **MUST** fail unless there is another public key associated with this Actor.
+        assert!(matches!(
+            reject(&mut state, &signed(revoke_key(&alice, &k1), &k1)),
+            Rule::LastKey
+        ));
+        state.apply(&signed(add_key(&alice, &k2), &k1)).unwrap();
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekey
+        //= type=test
+        //# The key
+        //# used to sign the `RevokeKey` cannot be the same as the key being revoked.
+        assert!(matches!(
+            reject(&mut state, &signed(revoke_key(&alice, &k1), &k1)),
+            Rule::SelfSigned
+        ));
+        assert!(matches!(
+            reject(&mut state, &signed(revoke_key(&alice, &k3), &k1)),
+            Rule::UnknownKey
+        ));
+        assert!(matches!(
+            reject(&mut state, &signed(revoke_key(&actor("bob"), &k1), &k1)),
+            Rule::UnknownActor(a) if a == actor("bob")
+        ));
+        state.apply(&signed(revoke_key(&alice, &k1), &k2)).unwrap();
+        assert_eq!(state.actor(&alice).unwrap().keys, vec![k2.public_key()]);
+        assert_eq!(state.len(), 3);
+
+        let mut unsigned = signed(add_key(&alice, &k3), &k2);
+        unsigned.recent_merkle_root = None;
+        assert!(matches!(
+            reject(&mut state, &unsigned),
+            Rule::Signature(crate::SignatureError::MissingMerkleRoot)
+        ));
+    }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekey
+    //= type=test
+    //# There is no undo operation for public key revocation.
+    #[test]
+    fn revoked_keys() {
+        let (alice, bob) = (actor("alice"), actor("bob"));
+        let (k1, k2, k3, k4) = (key(1), key(2), key(3), key(4));
+        let history = [
+            signed(add_key(&alice, &k1), &k1),
+            signed(add_key(&alice, &k2), &k1),
+            signed(revoke_key(&alice, &k1), &k2),
+            signed(add_key(&bob, &k3), &k3),
+            revoke_third_party(&k3),
+            signed(add_key(&bob, &k4), &k4),
+        ];
+        let mut state = State::replay(&history).unwrap();
+        let revoked = &state.actor(&alice).unwrap().revoked_keys;
+        assert_eq!(revoked.iter().collect::<Vec<_>>(), vec![&k1.public_key()]);
+
+        let readd = signed(add_key(&alice, &k1), &k2);
+        let divergence = State::replay(history.iter().chain([&readd])).unwrap_err();
+        assert_eq!(divergence.index, 6);
+        assert!(matches!(divergence.rule, Rule::RevokedKey));
+
+        // A key revoked by a third party is compromised for every actor
+        assert!(matches!(
+            reject(&mut state, &signed(add_key(&bob, &k3), &k4)),
+            Rule::RevokedKey
+        ));
+        assert!(matches!(
+            reject(&mut state, &signed(add_key(&alice, &k3), &k2)),
+            Rule::RevokedKey
+        ));
+
+        // Moving an identity doesn't clear the old actor's revocations
+        let moved = actor("alice2");
+        state
+            .apply(&signed(move_identity(&alice, &moved), &k2))
+            .unwrap();
+        assert!(matches!(
+            reject(&mut state, &signed(add_key(&alice, &k1), &k1)),
+            Rule::RevokedKey
+        ));
+        assert!(matches!(
+            reject(&mut state, &signed(add_key(&moved, &k1), &k2)),
+            Rule::RevokedKey
+        ));
+
+        // Nor does moving another identity onto an actor with no keys left
+        let (admin, k5) = (actor("admin"), key(5));
+        state.apply(&signed(add_key(&admin, &k5), &k5)).unwrap();
+        state
+            .apply(&signed(burn_down(&moved, &admin), &k5))
+            .unwrap();
+        state
+            .apply(&signed(move_identity(&bob, &moved), &k4))
+            .unwrap();
+        let revoked = &state.actor(&moved).unwrap().revoked_keys;
+        assert_eq!(revoked.iter().collect::<Vec<_>>(), vec![&k1.public_key()]);
+        assert!(matches!(
+            reject(&mut state, &signed(add_key(&moved, &k1), &k4)),
+            Rule::RevokedKey
+        ));
+    }
+
+    #[test]
+    fn fireproof() {
+        let alice = actor("alice");
+        let k1 = key(1);
+        let mut state = State::new();
+        state.apply(&signed(add_key(&alice, &k1), &k1)).unwrap();
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#undofireproof
+        //= type=test
+        //# If the user is not in `Fireproof` status, this message is rejected.
+        assert!(matches!(
+            reject(
+                &mut state,
+                &signed(Action::UndoFireproof(fireproof_message(&alice)), &k1)
+            ),
+            Rule::NotFireproof
+        ));
+        state
+            .apply(&signed(Action::Fireproof(fireproof_message(&alice)), &k1))
+            .unwrap();
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#fireproof
+        //= type=test
+        //# If the user is already in Fireproof status, this message is rejected.
+        assert!(matches!(
+            reject(
+                &mut state,
+                &signed(Action::Fireproof(fireproof_message(&alice)), &k1)
+            ),
+            Rule::AlreadyFireproof
+        ));
+        assert!(matches!(
+            reject(
+                &mut state,
+                &signed(Action::Fireproof(fireproof_message(&actor("bob"))), &k1)
+            ),
+            Rule::UnknownActor(_)
+        ));
+        state
+            .apply(&signed(
+                Action::UndoFireproof(fireproof_message(&alice)),
+                &k1,
+            ))
+            .unwrap();
+        assert!(!state.actor(&alice).unwrap().fireproof);
+    }
+
+    #[test]
+    fn burn_down_and_third_party() {
+        let (alice, admin) = (actor("alice"), actor("admin"));
+        let (k1, k2, k3) = (key(1), key(2), key(3));
+        let mut state = State::new();
+        state.apply(&signed(add_key(&alice, &k1), &k1)).unwrap();
+        state
+            .apply(&signed(add_aux(&alice, b"age1alice"), &k1))
+            .unwrap();
+        state.apply(&signed(add_key(&admin, &k3), &k3)).unwrap();
+
+        let foreign: ActorId = "https://example.org/users/admin".parse().unwrap();
+        assert!(matches!(
+            reject(&mut state, &signed(burn_down(&alice, &foreign), &k3)),
+            Rule::ForeignOperator(a) if a == foreign
+        ));
+        assert!(matches!(
+            reject(&mut state, &signed(burn_down(&alice, &admin), &k1)),
+            Rule::Signature(_)
+        ));
+        state
+            .apply(&signed(burn_down(&alice, &admin), &k3))
+            .unwrap();
+        let burned = state.actor(&alice).unwrap();
+        assert!(burned.keys.is_empty() && burned.aux_data.is_empty() && burned.burned_down);
+
+        // A burned down actor can start over with a self-signed AddKey
+        state.apply(&signed(add_key(&alice, &k2), &k2)).unwrap();
+        state
+            .apply(&signed(Action::Fireproof(fireproof_message(&alice)), &k2))
+            .unwrap();
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#burndown-validation-steps
+        //= type=test
+        //# If this actor is fireproof, abort.
+        assert!(matches!(
+            reject(&mut state, &signed(burn_down(&alice, &admin), &k3)),
+            Rule::Fireproof
+        ));
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekeythirdparty
+        //= type=test
+        //# Instead, the Actor will be treated as if they ran a successful
+        //# `BurnDown`, and allows them to start over with an `AddKey`.
+        state.apply(&revoke_third_party(&k2)).unwrap();
+        let revoked = state.actor(&alice).unwrap();
+        assert!(revoked.keys.is_empty() && revoked.burned_down && revoked.fireproof);
+        assert!(matches!(
+            reject(&mut state, &revoke_third_party(&k2)),
+            Rule::UnknownKey
+        ));
+        state.apply(&signed(add_key(&alice, &k1), &k1)).unwrap();
+        assert!(!state.actor(&alice).unwrap().burned_down);
+    }
+
+    #[test]
+    fn move_identity_and_aux() {
+        let (alice, moved, bob) = (actor("alice"), actor("alice2"), actor("bob"));
+        let (k1, k2) = (key(1), key(2));
+        let mut state = State::new().with_aux_types(["age-v1".to_string()]);
+        state.apply(&signed(add_key(&alice, &k1), &k1)).unwrap();
+        state.apply(&signed(add_key(&bob, &k2), &k2)).unwrap();
+        state
+            .apply(&signed(add_aux(&alice, b"age1alice"), &k1))
+            .unwrap();
+        assert!(matches!(
+            reject(&mut state, &signed(add_aux(&alice, b"age1alice"), &k1)),
+            Rule::DuplicateAuxData(_)
+        ));
+        assert!(matches!(
+            reject(&mut state, &signed(revoke_aux(&alice, None, None), &k1)),
+            Rule::MissingAuxId
+        ));
+        assert!(matches!(
+            reject(
+                &mut state,
+                &signed(
+                    revoke_aux(&alice, Some(b"age1alice"), Some("bogus".to_string())),
+                    &k1
+                )
+            ),
+            Rule::InvalidAuxId
+        ));
+        assert!(matches!(
+            reject(
+                &mut state,
+                &signed(revoke_aux(&alice, Some(b"age1bob"), None), &k1)
+            ),
+            Rule::UnknownAuxData(_)
+        ));
+
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#moveidentity
+        //= type=test
+        //# This message **MUST** be rejected if there are existing public keys for the target `new-actor`.
+        assert!(matches!(
+            reject(&mut state, &signed(move_identity(&alice, &bob), &k1)),
+            Rule::NewActorHasKeys
+        ));
+        assert!(matches!(
+            reject(&mut state, &signed(move_identity(&alice, &moved), &k2)),
+            Rule::Signature(_)
+        ));
+        state
+            .apply(&signed(move_identity(&alice, &moved), &k1))
+            .unwrap();
+        assert!(state.actor(&alice).unwrap().keys.is_empty());
+        let new = state.actor(&moved).unwrap();
+        assert_eq!(new.keys, vec![k1.public_key()]);
+        assert_eq!(new.aux_data.len(), 1);
+
+        let aux_id = crate::action::aux_data_id("age-v1", b"age1alice");
+        state
+            .apply(&signed(revoke_aux(&moved, None, Some(aux_id)), &k1))
+            .unwrap();
+        assert!(state.actor(&moved).unwrap().aux_data.is_empty());
+
+        let mut restricted = State::new().with_aux_types([]);
+        restricted
+            .apply(&signed(add_key(&alice, &k1), &k1))
+            .unwrap();
+        assert!(matches!(
+            reject(&mut restricted, &signed(add_aux(&alice, b"age1alice"), &k1)),
+            Rule::UnsupportedAuxType(t) if t == "age-v1"
+        ));
+    }
+}
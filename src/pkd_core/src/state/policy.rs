@@ -0,0 +1,190 @@
+use crate::action::Action;
+
+use super::{ActorState, Rule};
+
+/// The actions that interact with [Fireproof](https://github.com/fedi-e2ee/public-key-directory-specification/blob/main/Specification.md#fireproof)
+/// and account recovery
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Recovery {
+    /// [`Action::Fireproof`]
+    Fireproof,
+    /// [`Action::UndoFireproof`]
+    UndoFireproof,
+    /// [`Action::BurnDown`]
+    BurnDown,
+    /// [`Action::RevokeKeyThirdParty`], from the perspective of an actor holding the revoked key
+    RevokeKeyThirdParty,
+}
+
+impl Recovery {
+    /// Every [`Recovery`] action
+    pub const ALL: [Self; 4] = [
+        Self::Fireproof,
+        Self::UndoFireproof,
+        Self::BurnDown,
+        Self::RevokeKeyThirdParty,
+    ];
+
+    /// The [`Recovery`] action of `action`, if it is one
+    pub const fn of(action: &Action) -> Option<Self> {
+        match action {
+            Action::Fireproof(_) => Some(Self::Fireproof),
+            Action::UndoFireproof(_) => Some(Self::UndoFireproof),
+            Action::BurnDown { .. } => Some(Self::BurnDown),
+            Action::RevokeKeyThirdParty { .. } => Some(Self::RevokeKeyThirdParty),
+            _ => None,
+        }
+    }
+}
+
+/// The parts of an [`ActorState`] that [`evaluate`] depends on
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct ActorStatus {
+    /// The number of valid public keys
+    pub keys: usize,
+    /// Whether the actor has opted out of `BurnDown`
+    pub fireproof: bool,
+}
+
+impl From<&ActorState> for ActorStatus {
+    fn from(state: &ActorState) -> Self {
+        Self {
+            keys: state.keys.len(),
+            fireproof: state.fireproof,
+        }
+    }
+}
+
+/// What an accepted [`Recovery`] action does to an actor
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Effect {
+    /// The actor becomes Fireproof
+    Fireproof,
+    /// The actor is no longer Fireproof
+    UndoFireproof,
+    /// Every key and Auxiliary Data of the actor is cleared, allowing a self-signed `AddKey`.
+    ///
+    /// The actor's Fireproof status is unchanged.
+    BurnDown,
+    /// The revoked key is removed, and the actor keeps their other keys
+    RevokeKey,
+}
+
+/// Decide what `recovery` does to an actor with `status`, or which rule rejects it.
+///
+/// This only covers the interactions between Fireproof status and the actor's keys. Signatures, and whether the actor
+/// or operator exists, are checked separately.
+///
+/// # Example
+/// ```
+/// use pkd_core::state::{ActorStatus, Effect, Recovery, evaluate};
+///
+/// // A third party revoking the only key of a Fireproof actor burns them down regardless
+/// let status = ActorStatus { keys: 1, fireproof: true };
+/// assert_eq!(evaluate(Recovery::RevokeKeyThirdParty, status).unwrap(), Effect::BurnDown);
+/// assert!(evaluate(Recovery::BurnDown, status).is_err());
+/// ```
+pub fn evaluate(recovery: Recovery, status: ActorStatus) -> Result<Effect, Rule> {
+    match recovery {
+        // Fireproof and UndoFireproof are signed by the actor, so they need a key to sign with
+        Recovery::Fireproof | Recovery::UndoFireproof if status.keys == 0 => Err(Rule::NoKeys),
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#fireproof
+        //# If the user is already in Fireproof status, this message is rejected.
+        Recovery::Fireproof if status.fireproof => Err(Rule::AlreadyFireproof),
+        Recovery::Fireproof => Ok(Effect::Fireproof),
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#undofireproof
+        //# If the user is not in `Fireproof` status, this message is rejected.
+        Recovery::UndoFireproof if !status.fireproof => Err(Rule::NotFireproof),
+        Recovery::UndoFireproof => Ok(Effect::UndoFireproof),
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#burndown-validation-steps
+        //# If this actor is fireproof, abort.
+        Recovery::BurnDown if status.fireproof => Err(Rule::Fireproof),
+        Recovery::BurnDown => Ok(Effect::BurnDown),
+        Recovery::RevokeKeyThirdParty => match status.keys {
+            0 => Err(Rule::UnknownKey),
+            //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekeythirdparty
+            //# Since you need the secret key tog generate the revocation token for a given public key, `Fireproof` does not prevent
+            //# third parties from revoking public keys.
+            //
+            //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekeythirdparty
+            //# Instead, the Actor will be treated as if they ran a successful
+            //# `BurnDown`, and allows them to start over with an `AddKey`.
+            1 => Ok(Effect::BurnDown),
+            _ => Ok(Effect::RevokeKey),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ActorStatus, Effect, Recovery, evaluate};
+    use crate::state::Rule;
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#revokekeythirdparty
+    //= type=test
+    //# This does not cancel out a previous `Fireproof` status. Future `BurnDown` messages may fail.
+    #[test]
+    fn table() {
+        use Recovery::*;
+
+        // (action, keys, fireproof, expected)
+        let table: &[(Recovery, usize, bool, Result<Effect, Rule>)] = &[
+            (Fireproof, 0, false, Err(Rule::NoKeys)),
+            (Fireproof, 0, true, Err(Rule::NoKeys)),
+            (Fireproof, 1, false, Ok(Effect::Fireproof)),
+            (Fireproof, 1, true, Err(Rule::AlreadyFireproof)),
+            (Fireproof, 2, false, Ok(Effect::Fireproof)),
+            (Fireproof, 2, true, Err(Rule::AlreadyFireproof)),
+            (UndoFireproof, 0, false, Err(Rule::NoKeys)),
+            (UndoFireproof, 0, true, Err(Rule::NoKeys)),
+            (UndoFireproof, 1, false, Err(Rule::NotFireproof)),
+            (UndoFireproof, 1, true, Ok(Effect::UndoFireproof)),
+            (UndoFireproof, 2, false, Err(Rule::NotFireproof)),
+            (UndoFireproof, 2, true, Ok(Effect::UndoFireproof)),
+            (BurnDown, 0, false, Ok(Effect::BurnDown)),
+            (BurnDown, 0, true, Err(Rule::Fireproof)),
+            (BurnDown, 1, false, Ok(Effect::BurnDown)),
+            (BurnDown, 1, true, Err(Rule::Fireproof)),
+            (BurnDown, 2, false, Ok(Effect::BurnDown)),
+            (BurnDown, 2, true, Err(Rule::Fireproof)),
+            (RevokeKeyThirdParty, 0, false, Err(Rule::UnknownKey)),
+            (RevokeKeyThirdParty, 0, true, Err(Rule::UnknownKey)),
+            (RevokeKeyThirdParty, 1, false, Ok(Effect::BurnDown)),
+            (RevokeKeyThirdParty, 1, true, Ok(Effect::BurnDown)),
+            (RevokeKeyThirdParty, 2, false, Ok(Effect::RevokeKey)),
+            (RevokeKeyThirdParty, 2, true, Ok(Effect::RevokeKey)),
+        ];
+
+        // Every combination is covered exactly once
+        for recovery in Recovery::ALL {
+            for keys in 0..3 {
+                for fireproof in [false, true] {
+                    let rows = table
+                        .iter()
+                        .filter(|(r, k, f, _)| (*r, *k, *f) == (recovery, keys, fireproof))
+                        .count();
+                    assert_eq!(rows, 1, "{recovery:?} {keys} {fireproof}");
+                }
+            }
+        }
+
+        for (recovery, keys, fireproof, expected) in table {
+            let status = ActorStatus {
+                keys: *keys,
+                fireproof: *fireproof,
+            };
+            // `Rule` wraps errors that can't be compared, so rules are compared by variant
+            match (evaluate(*recovery, status), expected) {
+                (Ok(effect), Ok(expected)) => {
+                    assert_eq!(effect, *expected, "{recovery:?} {status:?}");
+                }
+                (Err(rule), Err(expected)) => assert_eq!(
+                    std::mem::discriminant(&rule),
+                    std::mem::discriminant(expected),
+                    "{recovery:?} {status:?}: {rule:?} != {expected:?}"
+                ),
+                (actual, _) => panic!("{recovery:?} {status:?}: {actual:?} != {expected:?}"),
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::{fmt::Display, ops::Deref, time::Duration};
+use std::{fmt::Display, ops::Deref, str::FromStr, time::Duration};
 
 use base64ct::{Base64UrlUnpadded, Encoding};
 
@@ -31,8 +31,10 @@ impl<P> Encrypted<P> {
     ///
     /// # Example
     /// ```
+    /// use pkd_core::Encrypted;
+    ///
     /// let cipher = vec![0x1];
-    /// let enc = Encrypted::from_ciphertext(cipher.clone())
+    /// let enc = Encrypted::<String>::from_ciphertext(cipher.clone());
     /// assert_eq!(enc.into_inner(), cipher)
     /// ```
     pub const fn from_ciphertext(ciphertext: Vec<u8>) -> Self {
@@ -46,8 +48,10 @@ impl<P> Encrypted<P> {
     ///
     /// # Example
     /// ```
+    /// use pkd_core::Encrypted;
+    ///
     /// let cipher = vec![0x1];
-    /// let enc = Encrypted::from_ciphertext(cipher.clone())
+    /// let enc = Encrypted::<String>::from_ciphertext(cipher.clone());
     /// assert_eq!(enc.into_inner(), cipher)
     /// ```
     pub fn into_inner(self) -> Vec<u8> {
@@ -55,42 +59,179 @@ impl<P> Encrypted<P> {
     }
 }
 
-/// A timestmap encoded in seconds since unix epoch
-#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
-#[serde(transparent)]
-pub struct Timestamp(String);
+/// Errors from parsing a [`Timestamp`]
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum TimestampError {
+    /// The string isn't a canonical base-10 integer, e.g. it is empty, signed or has leading zeros
+    #[error("timestamp isn't a canonical base-10 integer")]
+    NonCanonical,
+    /// The value doesn't fit in 64 bits
+    #[error("timestamp doesn't fit in 64 bits")]
+    Overflow,
+}
 
-impl Timestamp {
-    /// Get the current system [`Timestamp`]
-    ///
+/// A source of the current [`Timestamp`]
+///
+/// This lets tests pin the current time when checking [`Timestamp::is_within`].
+pub trait Clock {
+    /// The current [`Timestamp`]
+    fn now(&self) -> Timestamp;
+}
+
+/// A [`Clock`] backed by [`std::time::SystemTime`]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
     /// # Panics
     /// This function may panic if [`std::time::SystemTime::now`] returns a value before [`std::time::UNIX_EPOCH`].
-    pub fn now() -> Self {
+    fn now(&self) -> Timestamp {
         let now = std::time::SystemTime::now();
         let sec = now
             .duration_since(std::time::UNIX_EPOCH)
             .expect("system time to be after unix epoch");
-        Self(sec.as_secs().to_string())
+        Timestamp(sec.as_secs())
+    }
+}
+
+/// A fixed [`Clock`] that's always at this [`Timestamp`]
+impl Clock for Timestamp {
+    fn now(&self) -> Timestamp {
+        *self
+    }
+}
+
+//= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#timestamps
+//# All timestamps must be a string representing a 64-bit UNIX Timestamp, encoded in base-10. For example, `"1724364843"`.
+/// A timestamp encoded in seconds since unix epoch
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#handling-protocol-messages
+    //# Public Key Directories
+    //# **MAY** configure a preferred time window for timestamps, but it **MUST** be no greater than 30 days (2592000 seconds).
+    /// The largest window a protocol message's timestamp may be accepted in
+    pub const MAX_WINDOW: Duration = Duration::from_secs(2_592_000);
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#checkpoint-validation-steps
+    //# 4. Verify that `message.time` is within a reasonably recent time window (e.g. `86400` seconds, or 24 hours).
+    /// The window a [`Checkpoint`](crate::action::Checkpoint)'s timestamp is accepted in
+    pub const CHECKPOINT_WINDOW: Duration = Duration::from_secs(86_400);
+
+    /// Create a [`Timestamp`] from seconds since unix epoch
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(secs)
+    }
+
+    /// Get the current system [`Timestamp`]
+    ///
+    /// # Panics
+    /// This function may panic if [`std::time::SystemTime::now`] returns a value before [`std::time::UNIX_EPOCH`].
+    pub fn now() -> Self {
+        SystemClock.now()
     }
 
     /// Returns the [`Timestamp`] represnting unix epoch.
     #[allow(dead_code)]
-    pub(crate) fn epoch() -> Self {
-        Self("0".to_string())
+    pub(crate) const fn epoch() -> Self {
+        Self(0)
+    }
+
+    /// Returns the number of seconds since unix epoch
+    pub const fn as_secs(&self) -> u64 {
+        self.0
     }
 
     /// Returns the duration of this [`Timestamp`] since unix epoch
     ///
     /// # Example
     /// ```
+    /// use pkd_core::Timestamp;
+    ///
     /// let ts1 = Timestamp::now();
     /// std::thread::sleep(std::time::Duration::from_secs(1));
     /// let ts2 = Timestamp::now();
     /// assert!(ts2.since_epoch() >= ts1.since_epoch())
     /// ```
-    pub fn since_epoch(&self) -> Option<std::time::Duration> {
-        let secs: u64 = self.0.parse().ok()?;
-        Some(Duration::from_secs(secs))
+    pub const fn since_epoch(&self) -> Duration {
+        Duration::from_secs(self.0)
+    }
+
+    /// Whether this [`Timestamp`] is at most `window` away from `now`, in either direction.
+    ///
+    /// # Example
+    /// ```
+    /// use pkd_core::Timestamp;
+    /// use std::time::Duration;
+    ///
+    /// let now = Timestamp::from_secs(1724364843);
+    /// assert!(Timestamp::from_secs(1724364800).is_within(Duration::from_secs(60), now));
+    /// assert!(!Timestamp::from_secs(1724364700).is_within(Duration::from_secs(60), now));
+    /// ```
+    pub const fn is_within(&self, window: Duration, now: Timestamp) -> bool {
+        self.0.abs_diff(now.0) <= window.as_secs()
+    }
+
+    /// Whether this [`Timestamp`] is within [`Timestamp::MAX_WINDOW`] of `clock`'s current time
+    pub fn is_recent(&self, clock: &impl Clock) -> bool {
+        self.is_within(Self::MAX_WINDOW, clock.now())
+    }
+
+    /// Whether this [`Timestamp`] is within [`Timestamp::CHECKPOINT_WINDOW`] of `clock`'s current time
+    pub fn is_recent_checkpoint(&self, clock: &impl Clock) -> bool {
+        self.is_within(Self::CHECKPOINT_WINDOW, clock.now())
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = TimestampError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // `u64::from_str` accepts a leading `+` and leading zeros
+        let canonical = match s.as_bytes() {
+            [b'0'] => true,
+            [b'1'..=b'9', rest @ ..] => rest.iter().all(u8::is_ascii_digit),
+            _ => false,
+        };
+        if !canonical {
+            return Err(TimestampError::NonCanonical);
+        }
+        //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#timestamps
+        //# Implementations **MUST NOT** truncate UNIX timestamps to 32 bits.
+        s.parse().map(Self).map_err(|_| TimestampError::Overflow)
+    }
+}
+
+impl serde::Serialize for Timestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Timestamp {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TimestampVisitor;
+
+        impl serde::de::Visitor<'_> for TimestampVisitor {
+            type Value = Timestamp;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a base-10 UNIX timestamp string")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(TimestampVisitor)
     }
 }
 
@@ -106,10 +247,12 @@ pub struct Timestamped<T> {
 }
 
 impl<T> Timestamped<T> {
+    /// Wrap `inner` with the timestamp `time`
     pub const fn new(time: Timestamp, inner: T) -> Self {
         Self { time, inner }
     }
 
+    /// Wrap `inner` with the current system [`Timestamp`]
     pub fn now(inner: T) -> Self {
         Self {
             time: Timestamp::now(),
@@ -126,6 +269,72 @@ impl<T> Timestamped<T> {
     }
 }
 
+/// A tag for the value of a [`Context`]
+pub trait ContextValue {
+    /// The exact `!pkd-context` string
+    const CONTEXT: &'static str;
+}
+
+/// A `!pkd-context` domain separation string.
+///
+/// Deserialization fails unless the value is exactly [`ContextValue::CONTEXT`].
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub struct Context<T: ContextValue>(std::marker::PhantomData<T>);
+
+impl<T: ContextValue> Context<T> {
+    /// Create a new [`Context`]
+    pub const fn new() -> Self {
+        Self(std::marker::PhantomData)
+    }
+
+    /// Return the context string
+    pub const fn as_str(&self) -> &'static str {
+        T::CONTEXT
+    }
+}
+
+impl<T: ContextValue> Display for Context<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(T::CONTEXT)
+    }
+}
+
+impl<T: ContextValue> serde::Serialize for Context<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(T::CONTEXT)
+    }
+}
+
+impl<'de, T: ContextValue> serde::Deserialize<'de> for Context<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct ContextVisitor<T>(std::marker::PhantomData<T>);
+
+        impl<'de, T: ContextValue> serde::de::Visitor<'de> for ContextVisitor<T> {
+            type Value = Context<T>;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(formatter, "the string '{}'", T::CONTEXT)
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                if v == T::CONTEXT {
+                    Ok(Context::new())
+                } else {
+                    Err(E::invalid_value(serde::de::Unexpected::Str(v), &self))
+                }
+            }
+        }
+
+        deserializer.deserialize_str(ContextVisitor(std::marker::PhantomData))
+    }
+}
+
 pub trait PrefixedBase64Value {
     type Value: AsRef<[u8]> + for<'a> TryFrom<&'a [u8]>;
     const PREFIX: &'static str;
@@ -141,7 +350,7 @@ impl<T: PrefixedBase64Value> PrefixedBase64<T> {
     /// Create a new value of [`PrefixedBase64`]
     /// # Example
     /// ```
-    /// use pkd_core::key::ed25519::Ed25519Tag;
+    /// use pkd_core::{Ed25519Tag, PrefixedBase64};
     ///
     /// let key: [u8; 32] = [
     ///     0x4e, 0x6d, 0x97, 0x06, 0xf6, 0xf4, 0x98, 0x06, 0xf8, 0x95, 0xd5, 0x6e, 0x6c, 0x2c, 0xef,
@@ -172,6 +381,67 @@ impl<T: PrefixedBase64Value> From<PrefixedBase64<T>> for String {
     }
 }
 
+/// An error from parsing a [`PrefixedBase64`]
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum PrefixedBase64Error {
+    /// The value doesn't start with the expected prefix
+    #[error("expected value to start with '{0}:'")]
+    Prefix(&'static str),
+    /// The value isn't valid base64url
+    #[error("failed to decode base64url")]
+    Base64,
+    /// The encoded value has the wrong length
+    #[error("invalid encoded length, expected {expected} found {found}")]
+    EncodedLength {
+        /// The expected encoded length
+        expected: usize,
+        /// The actual encoded length
+        found: usize,
+    },
+    /// The decoded value has the wrong length
+    #[error("invalid key length, expected {expected} found {found}")]
+    Length {
+        /// The expected decoded length
+        expected: usize,
+        /// The actual decoded length
+        found: usize,
+    },
+}
+
+impl<T: PrefixedBase64Value> FromStr for PrefixedBase64<T> {
+    type Err = PrefixedBase64Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s
+            .strip_prefix(T::PREFIX)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .ok_or(PrefixedBase64Error::Prefix(T::PREFIX))?;
+        if rest.len() != T::ENCODED_LEN {
+            return Err(PrefixedBase64Error::EncodedLength {
+                expected: T::ENCODED_LEN,
+                found: rest.len(),
+            });
+        }
+        // HACK: can't use const generic parameter to make array
+        let mut key = vec![0; T::LEN];
+        let wrote = Base64UrlUnpadded::decode(rest, &mut key)
+            .map_err(|_| PrefixedBase64Error::Base64)?
+            .len();
+        if T::LEN != wrote {
+            return Err(PrefixedBase64Error::Length {
+                expected: T::LEN,
+                found: wrote,
+            });
+        }
+        T::Value::try_from(&key)
+            .map(PrefixedBase64)
+            .map_err(|_| PrefixedBase64Error::Length {
+                expected: T::LEN,
+                found: wrote,
+            })
+    }
+}
+
 impl<T: PrefixedBase64Value> serde::Serialize for PrefixedBase64<T> {
     //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#merkle-root-encoding
     //# Each Merkle Root will be encoded as an unpadded base64url string, prefixed with a distinct prefix for the current protocol version followed by a colon (currently, pkd-mr-v1:).
@@ -200,33 +470,7 @@ impl<'de, T: PrefixedBase64Value> serde::Deserialize<'de> for PrefixedBase64<T>
             where
                 E: serde::de::Error,
             {
-                let rest = v.strip_prefix(&format!("{}:", T::PREFIX)).ok_or_else(|| {
-                    E::custom(format!("expected value to start with '{}:'", T::PREFIX))
-                })?;
-                if rest.len() == T::ENCODED_LEN {
-                    // HACK: can't use const generic parameter to make array
-                    let mut key = vec![0; T::LEN];
-                    let wrote = Base64UrlUnpadded::decode(rest, &mut key)
-                        .map_err(|_| E::custom("failed to decode base64url"))?
-                        .len();
-                    if T::LEN == wrote {
-                        Ok(PrefixedBase64(T::Value::try_from(&key).map_err(|_| {
-                            E::custom("expected to instantiate value from bytes")
-                        })?))
-                    } else {
-                        Err(E::custom(format!(
-                            "invalid key length, expected {} found {}",
-                            T::LEN,
-                            wrote
-                        )))
-                    }
-                } else {
-                    Err(E::custom(format!(
-                        "invalid encoded length, expected {} found {}",
-                        T::ENCODED_LEN,
-                        rest.len()
-                    )))
-                }
+                v.parse().map_err(E::custom)
             }
         }
 
@@ -295,10 +539,12 @@ pub mod serde_base64_secrecy {
                 let secret = SecretBox::<Vec<u8>>::init_with_mut(|b| {
                     // SAFETY: We know that base64url encoding is always bigger than data
                     // Thus, we are sure we won't reallocate after this
-                    b.reserve_exact(v.len());
+                    b.resize(v.len(), 0);
                     ret = Base64UrlUnpadded::decode(v, b)
                         .map(|x| x.len())
                         .map_err(|_| E::custom("failed to decode base64url bytes"));
+                    // Shrinking never reallocates, the excess capacity is zeroized on drop
+                    b.truncate(*ret.as_ref().unwrap_or(&0));
                 });
                 ret.map(|_| secret)
             }
@@ -310,7 +556,19 @@ pub mod serde_base64_secrecy {
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::Encrypted;
+    use std::time::Duration;
+
+    use crate::utils::{Encrypted, Timestamp, TimestampError};
+
+    #[test]
+    fn decode_symmetric_key() {
+        let key: crate::action::SymmetricKey = serde_json::from_str("\"AQID\"").unwrap();
+        assert_eq!(
+            key,
+            crate::action::SymmetricKey::init(|v| v.extend([1, 2, 3]))
+        );
+        assert!(serde_json::from_str::<crate::action::SymmetricKey>("\"!\"").is_err());
+    }
 
     #[test]
     fn encode_encrypted() {
@@ -319,4 +577,46 @@ mod tests {
             "\"AQID\""
         );
     }
+
+    //= https://raw.githubusercontent.com/fedi-e2ee/public-key-directory-specification/refs/heads/main/Specification.md#timestamps
+    //= type=test
+    //# Implementations **MUST NOT** truncate UNIX timestamps to 32 bits.
+    #[test]
+    fn timestamp_serde() {
+        let ts: Timestamp = serde_json::from_str("\"1724364843\"").unwrap();
+        assert_eq!(ts, Timestamp::from_secs(1724364843));
+        assert_eq!(serde_json::to_string(&ts).unwrap(), "\"1724364843\"");
+
+        let max: Timestamp = serde_json::from_str("\"18446744073709551615\"").unwrap();
+        assert_eq!(max.as_secs(), u64::MAX);
+        assert_eq!("0".parse(), Ok(Timestamp::from_secs(0)));
+        assert_eq!(
+            "18446744073709551616".parse::<Timestamp>(),
+            Err(TimestampError::Overflow)
+        );
+
+        for bad in ["", "01", "+1", "-1", " 1", "1 ", "1.0", "1e3", "0x10"] {
+            assert_eq!(bad.parse::<Timestamp>(), Err(TimestampError::NonCanonical));
+        }
+        // Timestamps are strings, not JSON numbers
+        assert!(serde_json::from_str::<Timestamp>("1724364843").is_err());
+    }
+
+    #[test]
+    fn timestamp_window() {
+        let now = Timestamp::from_secs(10_000_000);
+        let day = Timestamp::CHECKPOINT_WINDOW.as_secs();
+        let month = Timestamp::MAX_WINDOW.as_secs();
+
+        assert!(now.is_within(Duration::ZERO, now));
+        assert!(Timestamp::from_secs(10_000_000 - day).is_recent_checkpoint(&now));
+        assert!(!Timestamp::from_secs(10_000_000 - day - 1).is_recent_checkpoint(&now));
+        assert!(Timestamp::from_secs(10_000_000 + day).is_recent_checkpoint(&now));
+        assert!(!Timestamp::from_secs(10_000_000 + day + 1).is_recent_checkpoint(&now));
+
+        assert!(Timestamp::from_secs(10_000_000 - month).is_recent(&now));
+        assert!(!Timestamp::from_secs(10_000_000 - month - 1).is_recent(&now));
+        assert!(!Timestamp::from_secs(u64::MAX).is_recent(&now));
+        assert!(Timestamp::now().is_recent(&crate::utils::SystemClock));
+    }
 }